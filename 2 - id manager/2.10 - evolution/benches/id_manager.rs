@@ -0,0 +1,241 @@
+use std::hint::black_box;
+use std::thread;
+
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkGroup, BenchmarkId, Criterion};
+
+use idmanager::ReusePolicy::{ReuseFast, ReuseSlow};
+use idmanager::{BitmapFreeSet, FreeSet, HybridFreeSet, IdManager, IdType, Intervals, ReusePolicy, UnsyncIdManager};
+
+const POLICIES: [ReusePolicy; 2] = [ReuseFast, ReuseSlow];
+
+const THREADS: usize = 4;
+
+// u8 can't hold many ids so every workload is sized to fit within its range
+
+fn workload_size<T: IdType>() -> u128 {
+    T::MAX.to_u128().min(10_000)
+}
+
+// a fixed xorshift so that every run frees the ids in the same "random" order
+
+fn shuffled<T: IdType>(count: u128) -> Vec<T> {
+    let mut ids: Vec<T> = (0..count).map(T::from_u128).collect();
+
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+
+    for i in (1..ids.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+
+        ids.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+
+    ids
+}
+
+fn allocated<T: IdType, S: FreeSet<T>>(reuse_policy: ReusePolicy, count: u128) -> UnsyncIdManager<T, S> {
+    let mut manager = UnsyncIdManager::<T, S>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+    for _ in 0..count {
+        manager.allocate();
+    }
+
+    manager
+}
+
+fn sequential<T: IdType>(c: &mut Criterion, type_name: &str) {
+    let count = workload_size::<T>();
+
+    let mut group = c.benchmark_group(format!("sequential/{}", type_name));
+
+    for reuse_policy in POLICIES {
+        group.bench_function(BenchmarkId::new("unsync", format!("{:?}", reuse_policy)), |b| {
+            b.iter(|| {
+                let mut manager = UnsyncIdManager::<T>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+                let ids: Vec<T> = (0..count).map(|_| manager.allocate()).collect();
+
+                for id in ids {
+                    manager.free(id);
+                }
+
+                black_box(manager)
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("thread_safe", format!("{:?}", reuse_policy)), |b| {
+            b.iter(|| {
+                let manager = IdManager::<T>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+                let ids: Vec<_> = (0..count).map(|_| manager.allocate_id()).collect();
+
+                drop(ids);
+
+                black_box(manager)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// frees every other id in a random order, leaving the free ids as many single value
+// intervals, and then allocates them all again
+
+fn fragmented<T: IdType>(c: &mut Criterion, type_name: &str) {
+    let count = workload_size::<T>();
+
+    let to_free: Vec<T> = shuffled::<T>(count).into_iter().filter(|id| id.to_u128() % 2 == 0).collect();
+
+    let mut group = c.benchmark_group(format!("fragmented/{}", type_name));
+
+    for reuse_policy in POLICIES {
+        fragmented_free_set::<T, Intervals<T>>(&mut group, "unsync", reuse_policy, count, &to_free);
+        fragmented_free_set::<T, BitmapFreeSet<T>>(&mut group, "unsync_bitmap", reuse_policy, count, &to_free);
+        fragmented_free_set::<T, HybridFreeSet<T>>(&mut group, "unsync_hybrid", reuse_policy, count, &to_free);
+
+        group.bench_function(BenchmarkId::new("thread_safe", format!("{:?}", reuse_policy)), |b| {
+            b.iter_batched(
+                || {
+                    let manager = IdManager::<T>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+                    // both policies hand out a new range in order so each id is at its own index
+
+                    let allocated: Vec<_> = (0..count).map(|_| Some(manager.allocate_id())).collect();
+
+                    (manager, allocated)
+                },
+                |(manager, mut allocated)| {
+                    for id in to_free.iter() {
+                        drop(allocated[id.to_u128() as usize].take());
+                    }
+
+                    let ids: Vec<_> = (0..to_free.len()).map(|_| manager.allocate_id()).collect();
+
+                    (manager, allocated, ids)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn fragmented_free_set<T: IdType, S: FreeSet<T>>(group: &mut BenchmarkGroup<'_, WallTime>, name: &str, reuse_policy: ReusePolicy, count: u128, to_free: &[T]) {
+    group.bench_function(BenchmarkId::new(name, format!("{:?}", reuse_policy)), |b| {
+        b.iter_batched(
+            || allocated::<T, S>(reuse_policy, count),
+            |mut manager| {
+                for id in to_free.iter() {
+                    manager.free(*id);
+                }
+
+                for _ in 0..to_free.len() {
+                    manager.allocate();
+                }
+
+                manager
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+// marks many small intervals as used, fragmenting the free ids, and then one interval
+// that covers all of them
+
+fn mark_interval_storm<T: IdType>(c: &mut Criterion, type_name: &str) {
+    let count = workload_size::<T>();
+
+    let mut group = c.benchmark_group(format!("mark_interval_storm/{}", type_name));
+
+    for reuse_policy in POLICIES {
+        group.bench_function(BenchmarkId::new("unsync", format!("{:?}", reuse_policy)), |b| {
+            b.iter(|| {
+                let mut manager = UnsyncIdManager::<T>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+                for lower in (0..count - 2).step_by(4) {
+                    manager.mark_interval_as_used(T::from_u128(lower), T::from_u128(lower + 1));
+                }
+
+                manager.mark_interval_as_used(T::MIN, T::from_u128(count - 1));
+
+                black_box(manager)
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("thread_safe", format!("{:?}", reuse_policy)), |b| {
+            b.iter(|| {
+                let manager = IdManager::<T>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+                for lower in (0..count - 2).step_by(4) {
+                    manager.mark_interval_as_used(T::from_u128(lower), T::from_u128(lower + 1));
+                }
+
+                manager.mark_interval_as_used(T::MIN, T::from_u128(count - 1));
+
+                black_box(manager)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// each thread repeatedly holds a handful of ids and then drops them
+
+fn churn<T: IdType>(c: &mut Criterion, type_name: &str) {
+    let count = workload_size::<T>();
+
+    let per_thread = (count / THREADS as u128 / 8) as usize;
+
+    let mut group = c.benchmark_group(format!("churn/{}", type_name));
+
+    for reuse_policy in POLICIES {
+        group.bench_function(BenchmarkId::new("thread_safe", format!("{:?}", reuse_policy)), |b| {
+            b.iter(|| {
+                let manager = IdManager::<T>::new_limited_range(reuse_policy, T::MIN, T::from_u128(count - 1));
+
+                thread::scope(|scope| {
+                    for _ in 0..THREADS {
+                        scope.spawn(|| {
+                            for _ in 0..8 {
+                                let ids: Vec<_> = (0..per_thread).map(|_| manager.allocate_id()).collect();
+
+                                drop(ids);
+                            }
+                        });
+                    }
+                });
+
+                black_box(manager)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+macro_rules! for_all_types {
+    ($c:expr, $bench:ident) => {
+        $bench::<u8>($c, "u8");
+        $bench::<u16>($c, "u16");
+        $bench::<u32>($c, "u32");
+        $bench::<u64>($c, "u64");
+        $bench::<u128>($c, "u128");
+        $bench::<usize>($c, "usize");
+    };
+}
+
+fn benches(c: &mut Criterion) {
+    for_all_types!(c, sequential);
+    for_all_types!(c, fragmented);
+    for_all_types!(c, mark_interval_storm);
+    for_all_types!(c, churn);
+}
+
+criterion_group!(id_manager, benches);
+criterion_main!(id_manager);
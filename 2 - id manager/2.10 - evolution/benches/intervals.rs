@@ -0,0 +1,138 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use idmanager::Intervals;
+
+const COUNT: u32 = 10_000;
+
+fn full() -> Intervals<u32> {
+    let mut intervals = Intervals::new();
+
+    intervals.insert_interval(0, COUNT - 1);
+
+    intervals
+}
+
+fn every_other() -> Intervals<u32> {
+    let mut intervals = Intervals::new();
+
+    for value in (0..COUNT).step_by(2) {
+        intervals.insert_value(value);
+    }
+
+    intervals
+}
+
+fn benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("intervals");
+
+    // allocating with ReuseFast
+
+    group.bench_function("remove_first_value", |b| {
+        b.iter_batched(
+            full,
+            |mut intervals| {
+                for _ in 0..COUNT {
+                    intervals.remove_first_value();
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // allocating with ReuseSlow
+
+    group.bench_function("remove_value_ascending", |b| {
+        b.iter_batched(
+            full,
+            |mut intervals| {
+                for value in 0..COUNT {
+                    intervals.remove_value(value);
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("remove_value_from_middle", |b| {
+        b.iter_batched(
+            full,
+            |mut intervals| {
+                for value in (0..COUNT).step_by(2) {
+                    intervals.remove_value(value);
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // freeing in the order that the ids were allocated and in the reverse order
+
+    group.bench_function("insert_value_ascending", |b| {
+        b.iter_batched(
+            Intervals::<u32>::new,
+            |mut intervals| {
+                for value in 0..COUNT {
+                    intervals.insert_value(value);
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("insert_value_descending", |b| {
+        b.iter_batched(
+            Intervals::<u32>::new,
+            |mut intervals| {
+                for value in (0..COUNT).rev() {
+                    intervals.insert_value(value);
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // freeing ids that join two intervals together
+
+    group.bench_function("insert_value_joining", |b| {
+        b.iter_batched(
+            every_other,
+            |mut intervals| {
+                for value in (1..COUNT).step_by(2) {
+                    intervals.insert_value(value);
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("remove_interval", |b| {
+        b.iter_batched(
+            every_other,
+            |mut intervals| {
+                for lower in (0..COUNT).step_by(100) {
+                    intervals.remove_interval(lower, lower + 49);
+                }
+
+                intervals
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(intervals, benches);
+criterion_main!(intervals);
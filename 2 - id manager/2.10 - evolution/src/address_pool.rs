@@ -0,0 +1,359 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::id_type::IdType;
+use crate::intervals::Intervals;
+use crate::parse_error::ParseError;
+use crate::reuse_policy::ReusePolicy;
+use crate::smart_id::SmartId;
+use crate::step::Step;
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+// An address family that an AddressPool can hand out, each address is held in the IdManager
+// as the integer with the same bits.
+
+pub trait PoolAddress: Step + FromStr {
+    type Id: IdType;
+
+    const BITS: u32;
+
+    // IPv6 has no broadcast address
+    const HAS_BROADCAST: bool;
+
+    fn to_id(self) -> Self::Id;
+
+    fn from_id(id: Self::Id) -> Self;
+}
+
+impl PoolAddress for Ipv4Addr {
+    type Id = u32;
+
+    const BITS: u32 = 32;
+
+    const HAS_BROADCAST: bool = true;
+
+    fn to_id(self) -> u32 {
+        self.into()
+    }
+
+    fn from_id(id: u32) -> Self {
+        id.into()
+    }
+}
+
+impl PoolAddress for Ipv6Addr {
+    type Id = u128;
+
+    const BITS: u32 = 128;
+
+    const HAS_BROADCAST: bool = false;
+
+    fn to_id(self) -> u128 {
+        self.into()
+    }
+
+    fn from_id(id: u128) -> Self {
+        id.into()
+    }
+}
+
+// Hands out the addresses of a subnet. The network address, and for IPv4 the broadcast
+// address, are never handed out unless the subnet is too small to have any other addresses.
+// Addresses are reused as slowly as possible so that a client that still holds a stale
+// address is unlikely to collide with a new lease.
+
+#[derive(Clone)]
+pub struct AddressPool<A: PoolAddress = Ipv4Addr> {
+    manager: ThreadSafeIdManager<A::Id>,
+    network: A,
+    prefix_len: u32,
+}
+
+impl<A: PoolAddress> AddressPool<A> {
+    // cidr is, for example, "192.168.1.0/24" or "fd00::/64"
+
+    pub fn new(cidr: &str) -> Result<Self, ParseError> {
+        let (address, prefix_len) = cidr.split_once('/').ok_or_else(|| ParseError::InvalidFormat(cidr.to_string()))?;
+
+        let network = address.trim().parse::<A>().map_err(|_| ParseError::InvalidValue(address.trim().to_string()))?;
+
+        let prefix_len = prefix_len.trim().parse::<u32>().map_err(|_| ParseError::InvalidValue(prefix_len.trim().to_string()))?;
+
+        Self::from_network(network, prefix_len)
+    }
+
+    pub fn from_network(network: A, prefix_len: u32) -> Result<Self, ParseError> {
+        if prefix_len > A::BITS {
+            return Err(ParseError::OutOfRange);
+        }
+
+        let host_bits = A::BITS - prefix_len;
+
+        let host_mask = if host_bits == 128 { u128::MAX } else { (1u128 << host_bits) - 1 };
+
+        let first = network.to_id().to_u128();
+
+        if first & host_mask != 0 {
+            return Err(ParseError::InvalidValue(network.to_string()));
+        }
+
+        let last = first | host_mask;
+
+        let manager = ThreadSafeIdManager::new_limited_range(ReusePolicy::ReuseSlow, A::Id::from_u128(first), A::Id::from_u128(last));
+
+        // a /31 or /32 (or /127 or /128) has no network or broadcast address
+
+        if host_bits >= 2 {
+            manager.mark_value_as_used(A::Id::from_u128(first));
+
+            if A::HAS_BROADCAST {
+                manager.mark_value_as_used(A::Id::from_u128(last));
+            }
+        }
+
+        Ok(AddressPool { manager, network, prefix_len })
+    }
+
+    pub fn network(&self) -> A {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u32 {
+        self.prefix_len
+    }
+
+    pub fn contains(&self, address: A) -> bool {
+        let (first, last) = self.bounds();
+
+        let address = address.to_id().to_u128();
+
+        first <= address && address <= last
+    }
+
+    // the first usable address is conventionally the gateway
+
+    pub fn exclude_gateway(&self) -> A {
+        let (first, last) = self.bounds();
+
+        let gateway = if last - first >= 2 { first + 1 } else { first };
+
+        self.manager.mark_value_as_used(A::Id::from_u128(gateway));
+
+        A::from_id(A::Id::from_u128(gateway))
+    }
+
+    pub fn exclude_address(&self, address: A) {
+        self.exclude(address, address);
+    }
+
+    pub fn exclude(&self, lower: A, upper: A) {
+        if !self.contains(lower) || !self.contains(upper) {
+            panic!("addresses are not in the subnet");
+        }
+
+        self.manager.mark_interval_as_used(lower.to_id(), upper.to_id());
+    }
+
+    pub fn lease(&self) -> Option<AddressLease<A>> {
+        let id = self.manager.try_allocate_id()?;
+
+        Some(AddressLease { id })
+    }
+
+    // frees an address that was kept with AddressLease::release
+
+    pub fn free(&self, released: ReleasedAddress<A>) {
+        if !self.contains(released.address) {
+            panic!("address is not in the subnet");
+        }
+
+        self.manager.free(released.address.to_id());
+    }
+
+    pub fn available(&self) -> u128 {
+        self.manager.stats().available()
+    }
+
+    // the free addresses, e.g. "[10.0.0.2,10.0.0.254]"
+
+    pub fn dump(&self) -> String {
+        let mut free = Intervals::<A>::new();
+
+        for interval in self.manager.snapshot().free_ids.iter() {
+            free.insert_interval(A::from_id(interval.lower()), A::from_id(interval.upper()));
+        }
+
+        free.dump()
+    }
+
+    fn bounds(&self) -> (u128, u128) {
+        let host_bits = A::BITS - self.prefix_len;
+
+        let first = self.network.to_id().to_u128();
+
+        let host_mask = if host_bits == 128 { u128::MAX } else { (1u128 << host_bits) - 1 };
+
+        (first, first | host_mask)
+    }
+}
+
+impl<A: PoolAddress> fmt::Display for AddressPool<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+// An address leased from an AddressPool, returned to the pool when dropped
+
+pub struct AddressLease<A: PoolAddress = Ipv4Addr> {
+    id: SmartId<A::Id>,
+}
+
+impl<A: PoolAddress> AddressLease<A> {
+    pub fn address(&self) -> A {
+        A::from_id(*self.id.value())
+    }
+
+    // keeps the address allocated, it must then be returned with AddressPool::free
+
+    pub fn release(mut self) -> ReleasedAddress<A> {
+        ReleasedAddress { address: A::from_id(self.id.release()) }
+    }
+}
+
+impl<A: PoolAddress> fmt::Display for AddressLease<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address())
+    }
+}
+
+// An address that is still allocated after its lease was released. Only AddressLease::release
+// creates one, so AddressPool::free can't be handed an address that was never leased.
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReleasedAddress<A: PoolAddress = Ipv4Addr> {
+    address: A,
+}
+
+impl<A: PoolAddress> ReleasedAddress<A> {
+    pub fn address(&self) -> A {
+        self.address
+    }
+}
+
+impl<A: PoolAddress> fmt::Display for ReleasedAddress<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let pool = AddressPool::<Ipv4Addr>::new("192.168.1.0/24").unwrap();
+
+        assert_eq!(pool.to_string(), "192.168.1.0/24");
+        assert_eq!(pool.dump(), "[192.168.1.1,192.168.1.254]");
+        assert_eq!(pool.available(), 254);
+    }
+
+    #[test]
+    fn test_new_invalid() {
+        assert_eq!(AddressPool::<Ipv4Addr>::new("192.168.1.0").err(), Some(ParseError::InvalidFormat("192.168.1.0".to_string())));
+        assert_eq!(AddressPool::<Ipv4Addr>::new("192.168.1/24").err(), Some(ParseError::InvalidValue("192.168.1".to_string())));
+        assert_eq!(AddressPool::<Ipv4Addr>::new("192.168.1.0/x").err(), Some(ParseError::InvalidValue("x".to_string())));
+        assert_eq!(AddressPool::<Ipv4Addr>::new("192.168.1.0/33").err(), Some(ParseError::OutOfRange));
+        assert_eq!(AddressPool::<Ipv4Addr>::new("192.168.1.1/24").err(), Some(ParseError::InvalidValue("192.168.1.1".to_string())));
+    }
+
+    #[test]
+    fn test_small_subnets() {
+        let pool = AddressPool::<Ipv4Addr>::new("10.0.0.0/30").unwrap();
+
+        assert_eq!(pool.dump(), "[10.0.0.1,10.0.0.2]");
+
+        let pool = AddressPool::<Ipv4Addr>::new("10.0.0.0/31").unwrap();
+
+        assert_eq!(pool.dump(), "[10.0.0.0,10.0.0.1]");
+
+        let pool = AddressPool::<Ipv4Addr>::new("10.0.0.7/32").unwrap();
+
+        assert_eq!(pool.dump(), "[10.0.0.7]");
+
+        let pool = AddressPool::<Ipv4Addr>::new("0.0.0.0/0").unwrap();
+
+        assert_eq!(pool.available(), (1 << 32) - 2);
+    }
+
+    #[test]
+    fn test_lease() {
+        let pool = AddressPool::<Ipv4Addr>::new("10.0.0.0/29").unwrap();
+
+        assert_eq!(pool.exclude_gateway(), Ipv4Addr::new(10, 0, 0, 1));
+
+        pool.exclude(Ipv4Addr::new(10, 0, 0, 5), Ipv4Addr::new(10, 0, 0, 6));
+
+        let first = pool.lease().unwrap();
+
+        assert_eq!(first.address(), Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(first.to_string(), "10.0.0.2");
+
+        let second = pool.lease().unwrap();
+        let third = pool.lease().unwrap();
+
+        assert_eq!(third.address(), Ipv4Addr::new(10, 0, 0, 4));
+
+        assert_eq!(pool.lease().is_none(), true);
+
+        drop(first);
+
+        assert_eq!(pool.dump(), "[10.0.0.2]");
+
+        let released = second.release();
+
+        assert_eq!(released.address(), Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(pool.dump(), "[10.0.0.2]");
+
+        pool.free(released);
+
+        assert_eq!(pool.dump(), "[10.0.0.2,10.0.0.3]");
+    }
+
+    #[test]
+    #[should_panic(expected = "address is not in the subnet")]
+    fn test_free_in_another_pool() {
+        let pool = AddressPool::<Ipv4Addr>::new("10.0.0.0/24").unwrap();
+        let other = AddressPool::<Ipv4Addr>::new("10.0.1.0/24").unwrap();
+
+        other.free(pool.lease().unwrap().release());
+    }
+
+    #[test]
+    fn test_ipv6() {
+        let pool = AddressPool::<Ipv6Addr>::new("fd00::/120").unwrap();
+
+        assert_eq!(pool.dump(), "[fd00::1,fd00::ff]");
+
+        assert_eq!(pool.exclude_gateway(), "fd00::1".parse::<Ipv6Addr>().unwrap());
+
+        assert_eq!(pool.lease().unwrap().to_string(), "fd00::2");
+
+        // the capacity of the whole address space saturates at u128::MAX
+
+        let pool = AddressPool::<Ipv6Addr>::new("::/0").unwrap();
+
+        assert_eq!(pool.available(), u128::MAX - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "addresses are not in the subnet")]
+    fn test_exclude_outside_subnet() {
+        let pool = AddressPool::<Ipv4Addr>::new("10.0.0.0/24").unwrap();
+
+        pool.exclude_address(Ipv4Addr::new(10, 0, 1, 0));
+    }
+}
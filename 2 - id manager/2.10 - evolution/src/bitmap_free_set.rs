@@ -0,0 +1,361 @@
+use std::marker::PhantomData;
+
+use crate::free_set::FreeSet;
+use crate::id_type::IdType;
+use crate::intervals::Intervals;
+
+// 2MB of bits, more than enough for any u8 or u16 range
+pub(crate) const MAX_BITS: u128 = 1 << 24;
+
+// One bit per value, set when the value is free. The bitmap only covers the values between
+// the lowest and highest values that have ever been inserted, rounded out to whole words.
+
+#[derive(Clone)]
+pub struct BitmapFreeSet<T: IdType> {
+    base: u128,             // the value of bit 0, always a multiple of 64
+    words: Vec<u64>,
+    count: u128,
+    marker: PhantomData<T>,
+}
+
+impl<T: IdType> BitmapFreeSet<T> {
+    pub fn new() -> Self {
+        BitmapFreeSet { base: 0, words: Vec::new(), count: 0, marker: PhantomData }
+    }
+
+    fn value(&self, offset: usize) -> T {
+        T::from_u128(self.base + offset as u128)
+    }
+
+    fn last(&self) -> u128 {
+        self.base + (self.words.len() as u128 * 64 - 1)
+    }
+
+    // the offsets of the values in [lower, upper] that the bitmap covers
+
+    fn offsets(&self, lower: T, upper: T) -> Option<(usize, usize)> {
+        let (lower, upper) = (lower.to_u128(), upper.to_u128());
+
+        if self.words.is_empty() || upper < self.base || lower > self.last() {
+            return None;
+        }
+
+        Some(((lower.max(self.base) - self.base) as usize, (upper.min(self.last()) - self.base) as usize))
+    }
+
+    fn covers(&self, lower: T, upper: T) -> bool {
+        !self.words.is_empty() && lower.to_u128() >= self.base && upper.to_u128() <= self.last()
+    }
+
+    fn grow_to_cover(&mut self, lower: u128, upper: u128) {
+        if self.words.is_empty() {
+            self.base = lower & !63;
+        } else if self.base <= lower && upper <= self.last() {
+            return;
+        }
+
+        let base = self.base.min(lower & !63);
+
+        let last = if self.words.is_empty() { upper } else { self.last().max(upper) };
+
+        let words = (last - base) / 64 + 1;
+
+        // words * 64 overflows when the range covers nearly all of u128
+
+        if words.checked_mul(64).is_none_or(|bits| bits > MAX_BITS) {
+            panic!("range is too large for a bitmap");
+        }
+
+        let shift = ((self.base - base) / 64) as usize;
+
+        let mut grown = vec![0u64; words as usize];
+
+        grown[shift..shift + self.words.len()].copy_from_slice(&self.words);
+
+        self.base = base;
+        self.words = grown;
+    }
+
+    // the words covering the offsets [lower, upper] along with a mask of the bits in each
+
+    fn masks(lower: usize, upper: usize) -> impl Iterator<Item = (usize, u64)> {
+        (lower / 64..=upper / 64).map(move |index| {
+            let first = if index == lower / 64 { lower % 64 } else { 0 };
+
+            let last = if index == upper / 64 { upper % 64 } else { 63 };
+
+            (index, (u64::MAX >> (63 - last)) & (u64::MAX << first))
+        })
+    }
+
+    fn count_in(&self, lower: usize, upper: usize) -> u128 {
+        Self::masks(lower, upper)
+            .map(|(index, mask)| (self.words[index] & mask).count_ones() as u128)
+            .sum()
+    }
+
+    fn next_matching(&self, from: usize, set: bool) -> Option<usize> {
+        let word = |index: usize| if set { self.words[index] } else { !self.words[index] };
+
+        let mut index = from / 64;
+
+        if index >= self.words.len() {
+            return None;
+        }
+
+        let mut bits = word(index) & (u64::MAX << (from % 64));
+
+        while bits == 0 {
+            index += 1;
+
+            if index == self.words.len() {
+                return None;
+            }
+
+            bits = word(index);
+        }
+
+        Some(index * 64 + bits.trailing_zeros() as usize)
+    }
+
+    // the offsets of each run of free values
+
+    fn runs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut from = 0;
+
+        std::iter::from_fn(move || {
+            let start = self.next_matching(from, true)?;
+
+            let end = self.next_matching(start, false).unwrap_or(self.words.len() * 64);
+
+            from = end;
+
+            Some((start, end - 1))
+        })
+    }
+}
+
+impl<T: IdType> Default for BitmapFreeSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: IdType> FreeSet<T> for BitmapFreeSet<T> {
+    fn from_intervals(intervals: &Intervals<T>) -> Self {
+        let mut bitmap = Self::new();
+
+        for interval in intervals.iter() {
+            bitmap.insert_interval(interval.lower(), interval.upper());
+        }
+
+        bitmap
+    }
+
+    fn to_intervals(&self) -> Intervals<T> {
+        let mut intervals = Intervals::new();
+
+        for (start, end) in self.runs() {
+            intervals.insert_interval(self.value(start), self.value(end));
+        }
+
+        intervals
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn value_count(&self) -> u128 {
+        self.count
+    }
+
+    fn interval_count(&self) -> usize {
+        self.runs().count()
+    }
+
+    fn largest_interval_len(&self) -> u128 {
+        self.runs().map(|(start, end)| (end - start + 1) as u128).max().unwrap_or(0)
+    }
+
+    fn insert_interval(&mut self, lower: T, upper: T) -> bool {
+        if upper < lower {
+            panic!("upper must be >= lower");
+        }
+
+        if let Some((first, last)) = self.offsets(lower, upper) {
+            if self.count_in(first, last) > 0 {
+                return false;
+            }
+        }
+
+        self.grow_to_cover(lower.to_u128(), upper.to_u128());
+
+        let (first, last) = self.offsets(lower, upper).unwrap();
+
+        for (index, mask) in Self::masks(first, last) {
+            self.words[index] |= mask;
+        }
+
+        self.count += (last - first + 1) as u128;
+
+        true
+    }
+
+    fn remove_interval(&mut self, lower: T, upper: T) -> u128 {
+        let Some((first, last)) = self.offsets(lower, upper) else {
+            return 0;
+        };
+
+        let mut removed = 0;
+
+        for (index, mask) in Self::masks(first, last) {
+            removed += (self.words[index] & mask).count_ones() as u128;
+
+            self.words[index] &= !mask;
+        }
+
+        self.count -= removed;
+
+        removed
+    }
+
+    fn remove_first_value_in(&mut self, lower: T, upper: T) -> Option<T> {
+        let (first, last) = self.offsets(lower, upper)?;
+
+        let offset = self.next_matching(first, true).filter(|offset| *offset <= last)?;
+
+        self.words[offset / 64] &= !(1 << (offset % 64));
+
+        self.count -= 1;
+
+        Some(self.value(offset))
+    }
+
+    fn contains_interval(&self, lower: T, upper: T) -> bool {
+        if !self.covers(lower, upper) {
+            return false;
+        }
+
+        let (first, last) = self.offsets(lower, upper).unwrap();
+
+        self.count_in(first, last) == (last - first + 1) as u128
+    }
+
+    fn find_interval_of_len(&self, len: u128) -> Option<T> {
+        self.runs()
+            .find(|(start, end)| (end - start + 1) as u128 >= len)
+            .map(|(start, _)| self.value(start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(dump: &str) -> BitmapFreeSet<u16> {
+        BitmapFreeSet::from_intervals(&dump.parse().unwrap())
+    }
+
+    #[test]
+    fn test_insert_interval() {
+        let mut bitmap = BitmapFreeSet::<u16>::new();
+
+        assert_eq!(bitmap.insert_interval(100, 200), true);
+        assert_eq!(bitmap.insert_interval(201, 300), true);
+        assert_eq!(bitmap.insert_interval(10, 20), true);
+        assert_eq!(bitmap.insert_interval(300, 301), false);
+        assert_eq!(bitmap.insert_value(65535), true);
+
+        assert_eq!(bitmap.dump(), "[10,20], [100,300], [65535]");
+
+        assert_eq!(bitmap.value_count(), 213);
+        assert_eq!(bitmap.interval_count(), 3);
+        assert_eq!(bitmap.largest_interval_len(), 201);
+    }
+
+    #[test]
+    fn test_remove_interval() {
+        let mut bitmap = bitmap("[10,20], [100,300]");
+
+        assert_eq!(bitmap.remove_interval(15, 150), 57);
+        assert_eq!(bitmap.remove_interval(0, 5), 0);
+        assert_eq!(bitmap.remove_interval(1000, 2000), 0);
+        assert_eq!(bitmap.remove_value(200), true);
+        assert_eq!(bitmap.remove_value(200), false);
+
+        assert_eq!(bitmap.dump(), "[10,14], [151,199], [201,300]");
+
+        assert_eq!(bitmap.value_count(), 154);
+    }
+
+    #[test]
+    fn test_remove_first_value_in() {
+        let mut bitmap = bitmap("[10,11], [100,300]");
+
+        assert_eq!(bitmap.remove_first_value(), Some(10));
+        assert_eq!(bitmap.remove_first_value_in(50, 99), None);
+        assert_eq!(bitmap.remove_first_value_in(50, 150), Some(100));
+        assert_eq!(bitmap.remove_first_value_in(0, 9), None);
+        assert_eq!(bitmap.remove_first_value_in(301, 65535), None);
+
+        assert_eq!(bitmap.dump(), "[11], [101,300]");
+    }
+
+    #[test]
+    fn test_contains_interval() {
+        let bitmap = bitmap("[10,20], [100,300]");
+
+        assert_eq!(bitmap.contains_interval(10, 20), true);
+        assert_eq!(bitmap.contains_interval(10, 21), false);
+        assert_eq!(bitmap.contains_interval(9, 10), false);
+        assert_eq!(bitmap.contains_interval(0, 5), false);
+        assert_eq!(bitmap.contains_interval(5000, 5000), false);
+    }
+
+    #[test]
+    fn test_find_interval_of_len() {
+        let bitmap = bitmap("[10,20], [30,32], [100,300]");
+
+        assert_eq!(bitmap.find_interval_of_len(3), Some(10));
+        assert_eq!(bitmap.find_interval_of_len(12), Some(100));
+        assert_eq!(bitmap.find_interval_of_len(202), None);
+    }
+
+    #[test]
+    fn test_grows_in_both_directions() {
+        let mut bitmap = BitmapFreeSet::<u8>::new();
+
+        assert_eq!(bitmap.insert_interval(100, 110), true);
+        assert_eq!(bitmap.insert_interval(0, 1), true);
+        assert_eq!(bitmap.insert_interval(250, 255), true);
+
+        assert_eq!(bitmap.dump(), "[0,1], [100,110], [250,255]");
+    }
+
+    #[test]
+    fn test_end_of_u128() {
+        let mut bitmap = BitmapFreeSet::<u128>::new();
+
+        assert_eq!(bitmap.insert_interval(u128::MAX - 10, u128::MAX), true);
+        assert_eq!(bitmap.remove_first_value_in(u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(bitmap.contains_interval(u128::MAX - 10, u128::MAX - 1), true);
+        assert_eq!(bitmap.value_count(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "range is too large for a bitmap")]
+    fn test_range_too_large() {
+        let mut bitmap = BitmapFreeSet::<u32>::new();
+
+        bitmap.insert_interval(0, u32::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "range is too large for a bitmap")]
+    fn test_range_too_large_for_u128() {
+        let mut bitmap = BitmapFreeSet::<u128>::new();
+
+        bitmap.insert_interval(0, u128::MAX);
+    }
+}
@@ -0,0 +1,9 @@
+use crate::id_type::IdType;
+
+// Something that a child manager can lease contiguous blocks of ids from and return them to.
+
+pub trait BlockSource<T: IdType>: Send + Sync {
+    fn lease_block(&self, size: u128) -> Option<(T, T)>;
+
+    fn return_block(&self, lower: T, upper: T);
+}
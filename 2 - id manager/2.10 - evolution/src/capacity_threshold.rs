@@ -0,0 +1,143 @@
+use crate::id_manager_stats::IdManagerStats;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CapacityThreshold {
+    Percent { low: u8, recovered: u8 },
+    Available { low: u128, recovered: u128 },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CapacityAlert {
+    Low(IdManagerStats),
+    Recovered(IdManagerStats),
+}
+
+impl CapacityThreshold {
+    fn validate(&self) {
+        match *self {
+            CapacityThreshold::Percent { low, recovered } => {
+                if recovered > 100 {
+                    panic!("percent must be <= 100");
+                }
+
+                if recovered < low {
+                    panic!("recovered must be >= low");
+                }
+            }
+            CapacityThreshold::Available { low, recovered } => {
+                if recovered < low {
+                    panic!("recovered must be >= low");
+                }
+            }
+        }
+    }
+
+    // returns the number of available ids below which we are low and the number at or above
+    // which we have recovered
+
+    pub fn limits(&self, capacity: u128) -> (u128, u128) {
+        match *self {
+            CapacityThreshold::Percent { low, recovered } => {
+                (percent_of(capacity, low), percent_of(capacity, recovered))
+            }
+            CapacityThreshold::Available { low, recovered } => (low, recovered),
+        }
+    }
+}
+
+fn percent_of(value: u128, percent: u8) -> u128 {
+    let percent = percent as u128;
+
+    // avoids overflow for the largest capacities
+
+    value / 100 * percent + value % 100 * percent / 100
+}
+
+pub(crate) struct Watermark {
+    threshold: CapacityThreshold,
+    is_low: bool,
+}
+
+impl Watermark {
+    pub fn new(threshold: CapacityThreshold) -> Self {
+        threshold.validate();
+
+        Watermark { threshold, is_low: false }
+    }
+
+    // the stats are only needed, and so only gathered, when an alert is raised
+
+    pub fn check<F: FnOnce() -> IdManagerStats>(&mut self, capacity: u128, available: u128, stats: F) -> Option<CapacityAlert> {
+        let (low, recovered) = self.threshold.limits(capacity);
+
+        if !self.is_low && available < low {
+            self.is_low = true;
+
+            return Some(CapacityAlert::Low(stats()));
+        }
+
+        if self.is_low && available >= recovered {
+            self.is_low = false;
+
+            return Some(CapacityAlert::Recovered(stats()));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_with_available(available: u128) -> IdManagerStats {
+        IdManagerStats {
+            capacity: 100,
+            allocated: 100 - available,
+            reserved: 0,
+            peak_allocated: 100 - available,
+            free_intervals: 1,
+            largest_free_run: available,
+            wrap_arounds: 0,
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "recovered must be >= low")]
+    fn test_recovered_less_than_low() {
+        let _watermark = Watermark::new(CapacityThreshold::Available { low: 10, recovered: 9 });
+    }
+
+    #[test]
+    #[should_panic(expected = "percent must be <= 100")]
+    fn test_percent_more_than_100() {
+        let _watermark = Watermark::new(CapacityThreshold::Percent { low: 10, recovered: 101 });
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(CapacityThreshold::Percent { low: 10, recovered: 20 }.limits(41), (4, 8));
+        assert_eq!(CapacityThreshold::Percent { low: 10, recovered: 20 }.limits(256), (25, 51));
+        assert_eq!(CapacityThreshold::Percent { low: 0, recovered: 100 }.limits(u128::MAX), (0, u128::MAX));
+        assert_eq!(CapacityThreshold::Available { low: 5, recovered: 7 }.limits(256), (5, 7));
+    }
+
+    fn check(watermark: &mut Watermark, available: u128) -> Option<CapacityAlert> {
+        watermark.check(100, available, || stats_with_available(available))
+    }
+
+    #[test]
+    fn test_check_fires_once_on_crossing_and_once_on_recovery() {
+        let mut watermark = Watermark::new(CapacityThreshold::Percent { low: 10, recovered: 20 });
+
+        assert_eq!(check(&mut watermark, 50), None);
+        assert_eq!(check(&mut watermark, 10), None);
+        assert_eq!(check(&mut watermark, 9), Some(CapacityAlert::Low(stats_with_available(9))));
+        assert_eq!(check(&mut watermark, 5), None);
+        assert_eq!(check(&mut watermark, 15), None);
+        assert_eq!(check(&mut watermark, 9), None);
+        assert_eq!(check(&mut watermark, 20), Some(CapacityAlert::Recovered(stats_with_available(20))));
+        assert_eq!(check(&mut watermark, 50), None);
+        assert_eq!(check(&mut watermark, 9), Some(CapacityAlert::Low(stats_with_available(9))));
+    }
+}
@@ -0,0 +1,234 @@
+extern crate idmanager;
+
+use std::env;
+use std::process;
+
+use idmanager::{IdManagerSnapshot, IdType, Intervals, RangeOrder, ReusePolicy, UnsyncIdManager};
+
+const USAGE: &str = "usage: id-inspect [--type u8|u16|u32|u64|u128|usize] [--range <lower>-<upper>] <snapshot or dump> [operation...]
+
+The state is either a snapshot, as produced by IdManager::snapshot(), or the output of
+IdManager::dump() along with the --range that the manager covers.
+
+operations:
+  allocate              allocate the next id, it is left allocated
+  free <id>             free an allocated id
+  mark <id>             mark an id as used
+  mark <lower>-<upper>  mark an interval of ids as used
+  stats                 display the current stats";
+
+struct Options {
+    id_type: String,
+    range: Option<String>,
+    state: String,
+    operations: Vec<String>,
+}
+
+pub fn main() {
+    let options = match parse_options(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(reason) => {
+            eprintln!("{}\n\n{}", reason, USAGE);
+            process::exit(1);
+        }
+    };
+
+    let result = match options.id_type.as_str() {
+        "u8" => inspect::<u8>(&options),
+        "u16" => inspect::<u16>(&options),
+        "u32" => inspect::<u32>(&options),
+        "u64" => inspect::<u64>(&options),
+        "u128" => inspect::<u128>(&options),
+        "usize" => inspect::<usize>(&options),
+        id_type => Err(format!("unsupported type: {}", id_type)),
+    };
+
+    if let Err(reason) = result {
+        eprintln!("error: {}", reason);
+        process::exit(1);
+    }
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut id_type = "u64".to_string();
+    let mut range = None;
+    let mut args = args.into_iter();
+    let mut state = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--type" => id_type = args.next().ok_or("--type requires a value")?,
+            "--range" => range = Some(args.next().ok_or("--range requires a value")?),
+            _ => {
+                state = Some(arg);
+                break;
+            }
+        }
+    }
+
+    let state = state.ok_or("no snapshot or dump given")?;
+
+    Ok(Options { id_type, range, state, operations: args.collect() })
+}
+
+fn parse_value<T: IdType>(text: &str) -> Result<T, String> {
+    text.trim().parse::<T>().map_err(|_| format!("invalid id: {}", text))
+}
+
+fn parse_interval<T: IdType>(text: &str) -> Result<(T, T), String> {
+    let (lower, upper) = match text.split_once('-') {
+        Some((lower, upper)) => (parse_value(lower)?, parse_value(upper)?),
+        None => {
+            let value = parse_value(text)?;
+
+            (value, value)
+        }
+    };
+
+    if upper < lower {
+        return Err(format!("upper must be >= lower: {}", text));
+    }
+
+    Ok((lower, upper))
+}
+
+fn load<T: IdType>(options: &Options) -> Result<UnsyncIdManager<T>, String> {
+    let snapshot = if options.state.contains('=') {
+        options.state.parse::<IdManagerSnapshot<T>>().map_err(|error| error.to_string())?
+    } else {
+        let (lower, upper) = match &options.range {
+            Some(range) => parse_interval::<T>(range)?,
+            None => (T::MIN, T::MAX),
+        };
+
+        let mut ranges = Intervals::<T>::new();
+
+        ranges.insert_interval(lower, upper);
+
+        IdManagerSnapshot {
+            reuse_policy: ReusePolicy::ReuseSlow,
+            range_order: RangeOrder::Ascending,
+            ranges,
+            next_to_allocate: lower,
+            free_ids: options.state.parse::<Intervals<T>>().map_err(|error| error.to_string())?,
+        }
+    };
+
+    UnsyncIdManager::<T>::from_snapshot(snapshot).map_err(|error| error.to_string())
+}
+
+fn allocated_ids<T: IdType>(snapshot: &IdManagerSnapshot<T>) -> Intervals<T> {
+    let mut allocated = snapshot.ranges.clone();
+
+    for interval in snapshot.free_ids.iter() {
+        allocated.remove_interval(interval.lower(), interval.upper());
+    }
+
+    allocated
+}
+
+fn display_state<T: IdType>(manager: &UnsyncIdManager<T>) {
+    let snapshot = manager.snapshot();
+
+    println!("ranges:    {}", snapshot.ranges);
+    println!("free:      {}", snapshot.free_ids);
+    println!("allocated: {}", allocated_ids(&snapshot));
+
+    display_stats(manager);
+}
+
+fn display_stats<T: IdType>(manager: &UnsyncIdManager<T>) {
+    let stats = manager.stats();
+
+    println!("capacity {}, allocated {} (peak {}), available {}", stats.capacity, stats.allocated, stats.peak_allocated, stats.available());
+    println!("fragmentation: {} free intervals, largest free run {}", stats.free_intervals, stats.largest_free_run);
+}
+
+fn apply<T: IdType>(manager: &mut UnsyncIdManager<T>, operation: &str) -> Result<(), String> {
+    let parts: Vec<&str> = operation.split_whitespace().collect();
+
+    let snapshot = manager.snapshot();
+
+    match parts.as_slice() {
+        ["allocate"] => {
+            if !manager.can_allocate() {
+                return Err("no ids available".to_string());
+            }
+
+            println!("allocated {}", manager.allocate());
+        }
+        ["free", id] => {
+            let id = parse_value::<T>(id)?;
+
+            if !allocated_ids(&snapshot).contains_interval(id, id) {
+                return Err(format!("{} is not allocated", id));
+            }
+
+            manager.free(id);
+        }
+        ["mark", interval] => {
+            let (lower, upper) = parse_interval::<T>(interval)?;
+
+            if !snapshot.ranges.contains_interval(lower, upper) {
+                return Err(format!("{} is out of range", interval));
+            }
+
+            manager.mark_interval_as_used(lower, upper);
+        }
+        ["stats"] => display_stats(manager),
+        _ => return Err(format!("unknown operation: {}", operation)),
+    }
+
+    Ok(())
+}
+
+fn inspect<T: IdType>(options: &Options) -> Result<(), String> {
+    let mut manager = load::<T>(options)?;
+
+    display_state(&manager);
+
+    for operation in options.operations.iter() {
+        println!("> {}", operation);
+
+        apply(&mut manager, operation)?;
+
+        println!("{}", manager.dump());
+    }
+
+    if !options.operations.is_empty() {
+        display_state(&manager);
+    }
+
+    println!("snapshot:  {}", manager.snapshot());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(range: &str, state: &str) -> Options {
+        Options { id_type: "u8".to_string(), range: Some(range.to_string()), state: state.to_string(), operations: Vec::new() }
+    }
+
+    #[test]
+    fn test_load() {
+        let manager = load::<u8>(&options("10-210", "[10], [13,200]")).unwrap();
+
+        assert_eq!(manager.dump(), "[10], [13,200]");
+
+        assert_eq!(load::<u8>(&options("10-210", "[5]")).err(), Some("value is out of range".to_string()));
+    }
+
+    #[test]
+    fn test_reversed_intervals() {
+        assert_eq!(load::<u8>(&options("210-10", "[10]")).err(), Some("upper must be >= lower: 210-10".to_string()));
+
+        let mut manager = load::<u8>(&options("10-210", "[10,210]")).unwrap();
+
+        assert_eq!(apply(&mut manager, "mark 205-201"), Err("upper must be >= lower: 205-201".to_string()));
+
+        assert_eq!(manager.dump(), "[10,210]");
+    }
+}
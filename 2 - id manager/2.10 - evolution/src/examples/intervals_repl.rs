@@ -0,0 +1,315 @@
+extern crate idmanager;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::process;
+
+use idmanager::{IdType, Intervals};
+
+const USAGE: &str = "usage: intervals-repl [--type u8|u16|u32|u64|u128|usize] [--script <file>]
+
+Reads commands from stdin, printing the dump of the intervals after each one.
+
+commands:
+  insert <value>|<lower>-<upper>    insert values, they must not already be present
+  remove <value>|<lower>-<upper>    remove values, any that are not present are ignored
+  union <dump>                      insert all of the values in a dump, e.g. [1,5], [9]
+  contains <value>|<lower>-<upper>  display whether all of the values are present
+  clear                             remove all values
+  dump                              display the dump
+
+A script is a session transcript, commands are optionally prefixed with '> ' and the
+expected output of the preceding command is given on a line starting with '= '. Blank
+lines and lines starting with '#' are ignored, for example:
+
+  > insert 10-20
+  = [10,20]
+  > remove 15
+  = [10,14], [16,20]
+  > contains 15
+  = false";
+
+struct Options {
+    id_type: String,
+    script: Option<String>,
+}
+
+pub fn main() {
+    let options = match parse_options(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(reason) => {
+            eprintln!("{}\n\n{}", reason, USAGE);
+            process::exit(1);
+        }
+    };
+
+    let result = match options.id_type.as_str() {
+        "u8" => start::<u8>(&options),
+        "u16" => start::<u16>(&options),
+        "u32" => start::<u32>(&options),
+        "u64" => start::<u64>(&options),
+        "u128" => start::<u128>(&options),
+        "usize" => start::<usize>(&options),
+        id_type => Err(format!("unsupported type: {}", id_type)),
+    };
+
+    if let Err(reason) = result {
+        eprintln!("error: {}", reason);
+        process::exit(1);
+    }
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut id_type = "u64".to_string();
+    let mut script = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--type" => id_type = args.next().ok_or("--type requires a value")?,
+            "--script" => script = Some(args.next().ok_or("--script requires a value")?),
+            arg => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    Ok(Options { id_type, script })
+}
+
+fn start<T: IdType>(options: &Options) -> Result<(), String> {
+    match &options.script {
+        Some(path) => {
+            let script = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+
+            let (commands, checks) = replay::<T>(&script)?;
+
+            println!("ok: {} commands, {} checks", commands, checks);
+
+            Ok(())
+        }
+        None => repl::<T>(),
+    }
+}
+
+fn parse_value<T: IdType>(text: &str) -> Result<T, String> {
+    text.trim().parse::<T>().map_err(|_| format!("invalid value: {}", text))
+}
+
+fn parse_interval<T: IdType>(text: &str) -> Result<(T, T), String> {
+    let (lower, upper) = match text.split_once('-') {
+        Some((lower, upper)) => (parse_value(lower)?, parse_value(upper)?),
+        None => {
+            let value = parse_value(text)?;
+
+            (value, value)
+        }
+    };
+
+    if upper < lower {
+        return Err(format!("upper must be >= lower: {}", text));
+    }
+
+    Ok((lower, upper))
+}
+
+fn execute<T: IdType>(intervals: &mut Intervals<T>, command: &str) -> Result<String, String> {
+    let (name, argument) = match command.split_once(' ') {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+
+    match (name, argument) {
+        ("insert", interval) if !interval.is_empty() => {
+            let (lower, upper) = parse_interval::<T>(interval)?;
+
+            if !intervals.insert_interval(lower, upper) {
+                return Err(format!("{} overlaps existing values", interval));
+            }
+        }
+        ("remove", interval) if !interval.is_empty() => {
+            let (lower, upper) = parse_interval::<T>(interval)?;
+
+            intervals.remove_interval(lower, upper);
+        }
+        ("union", dump) => {
+            let other = dump.parse::<Intervals<T>>().map_err(|error| error.to_string())?;
+
+            intervals.union(&other);
+        }
+        ("contains", interval) if !interval.is_empty() => {
+            let (lower, upper) = parse_interval::<T>(interval)?;
+
+            return Ok(intervals.contains_interval(lower, upper).to_string());
+        }
+        ("clear", "") => *intervals = Intervals::new(),
+        ("dump", "") => {}
+        _ => return Err(format!("unknown command: {}", command)),
+    }
+
+    Ok(intervals.dump())
+}
+
+fn output<T: IdType>(intervals: &mut Intervals<T>, command: &str) -> String {
+    match execute(intervals, command) {
+        Ok(output) => output,
+        Err(reason) => format!("error: {}", reason),
+    }
+}
+
+fn repl<T: IdType>() -> Result<(), String> {
+    let mut intervals = Intervals::<T>::new();
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    loop {
+        print!("> ");
+
+        stdout.flush().map_err(|error| error.to_string())?;
+
+        let mut line = String::new();
+
+        if stdin.lock().read_line(&mut line).map_err(|error| error.to_string())? == 0 {
+            println!();
+
+            return Ok(());
+        }
+
+        let command = line.trim();
+
+        if command.is_empty() {
+            continue;
+        }
+
+        if command == "quit" || command == "exit" {
+            return Ok(());
+        }
+
+        println!("= {}", output(&mut intervals, command));
+    }
+}
+
+fn replay<T: IdType>(script: &str) -> Result<(usize, usize), String> {
+    let mut intervals = Intervals::<T>::new();
+
+    let mut last_output = None;
+    let mut commands = 0;
+    let mut checks = 0;
+
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.strip_prefix('=') {
+            Some(expected) => {
+                let actual: &String = last_output
+                    .as_ref()
+                    .ok_or_else(|| format!("line {}: expected output before any command", number + 1))?;
+
+                if actual != expected.trim() {
+                    return Err(format!("line {}: expected '{}', got '{}'", number + 1, expected.trim(), actual));
+                }
+
+                checks += 1;
+            }
+            None => {
+                let command = line.strip_prefix('>').unwrap_or(line).trim();
+
+                last_output = Some(output(&mut intervals, command));
+
+                commands += 1;
+            }
+        }
+    }
+
+    Ok((commands, checks))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(output(&mut intervals, "insert 10-20"), "[10,20]");
+        assert_eq!(output(&mut intervals, "insert 21"), "[10,21]");
+        assert_eq!(output(&mut intervals, "insert 15-30"), "error: 15-30 overlaps existing values");
+        assert_eq!(output(&mut intervals, "remove 12-13"), "[10,11], [14,21]");
+        assert_eq!(output(&mut intervals, "union [0,10], [12], [100]"), "[0,12], [14,21], [100]");
+        assert_eq!(output(&mut intervals, "contains 12"), "true");
+        assert_eq!(output(&mut intervals, "contains 12-14"), "false");
+        assert_eq!(output(&mut intervals, "insert 256"), "error: invalid value: 256");
+        assert_eq!(output(&mut intervals, "insert 20-10"), "error: upper must be >= lower: 20-10");
+        assert_eq!(output(&mut intervals, "union [5,1]"), "error: upper must be >= lower: [5,1]");
+        assert_eq!(output(&mut intervals, "insert"), "error: unknown command: insert");
+        assert_eq!(output(&mut intervals, "dump"), "[0,12], [14,21], [100]");
+        assert_eq!(output(&mut intervals, "clear"), "");
+    }
+
+    #[test]
+    fn test_replay() {
+        let script = "
+            # merging and splitting
+            > insert 10-20
+            = [10,20]
+            > remove 15
+            = [10,14], [16,20]
+            > insert 15
+            = [10,20]
+
+            insert 0-255
+            = error: 0-255 overlaps existing values
+            > clear
+            =
+            > contains 1
+            = false";
+
+        assert_eq!(replay::<u8>(script), Ok((6, 6)));
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let script = "
+            > insert 10-20
+            = [10,20]
+            > remove 15
+            = [10,20]";
+
+        assert_eq!(replay::<u8>(script), Err("line 5: expected '[10,20]', got '[10,14], [16,20]'".to_string()));
+
+        assert_eq!(replay::<u8>("= [10,20]"), Err("line 1: expected output before any command".to_string()));
+    }
+
+    // the checked in scripts are replayed with the default type, as by intervals-repl --script
+
+    #[test]
+    fn test_scripts() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/examples/scripts");
+
+        let mut replayed = 0;
+
+        for entry in fs::read_dir(&directory).unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.extension().is_some_and(|extension| extension == "txt") {
+                let script = fs::read_to_string(&path).unwrap();
+
+                let (commands, checks) = replay::<u64>(&script).unwrap_or_else(|reason| panic!("{}: {}", path.display(), reason));
+
+                assert_eq!(commands, checks, "{}: the output of every command should be checked", path.display());
+
+                replayed += 1;
+            }
+        }
+
+        assert_ne!(replayed, 0);
+    }
+}
@@ -0,0 +1,54 @@
+extern crate idmanager;
+
+#[cfg(unix)]
+pub fn main() {
+    use std::env;
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use idmanager::IdManager;
+    use idmanager::LeaseServer;
+    use idmanager::ReusePolicy::ReuseSlow;
+
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() != 5 {
+        eprintln!("usage: {} <socket path> <min id> <max id> <lease ttl seconds>", args[0]);
+        process::exit(1);
+    }
+
+    let path = &args[1];
+    let min_id: u64 = args[2].parse().expect("invalid min id");
+    let max_id: u64 = args[3].parse().expect("invalid max id");
+    let ttl = Duration::from_secs(args[4].parse().expect("invalid lease ttl"));
+
+    let manager = IdManager::<u64>::new_limited_range(ReuseSlow, min_id, max_id);
+
+    let server = Arc::new(LeaseServer::new(manager, ttl));
+
+    let reaper = server.clone();
+
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+
+        let reaped = reaper.reap_expired(Instant::now());
+
+        if reaped != 0 {
+            println!("reclaimed {} expired blocks, free: {}", reaped, reaper.manager().dump());
+        }
+    });
+
+    let listener = UnixListener::bind(path).expect("failed to bind to socket");
+
+    println!("serving ids [{},{}] on {}", min_id, max_id, path);
+
+    server.serve(listener).expect("failed to accept connection");
+}
+
+#[cfg(not(unix))]
+pub fn main() {
+    eprintln!("lease-server requires Unix domain sockets");
+}
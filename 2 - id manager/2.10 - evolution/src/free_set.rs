@@ -0,0 +1,158 @@
+use crate::id_type::IdType;
+use crate::interval::Interval;
+use crate::intervals::Intervals;
+use crate::validation_report::ValidationReport;
+
+// The set of free ids held by an IdManager. Intervals suits sparse or lightly fragmented
+// sets, BitmapFreeSet suits small ranges that become heavily fragmented, and HybridFreeSet
+// switches between the two as the fragmentation changes.
+
+pub trait FreeSet<T: IdType>: Clone + Send + 'static {
+    fn from_intervals(intervals: &Intervals<T>) -> Self;
+
+    fn to_intervals(&self) -> Intervals<T>;
+
+    fn is_empty(&self) -> bool;
+
+    fn value_count(&self) -> u128;
+
+    fn interval_count(&self) -> usize;
+
+    fn largest_interval_len(&self) -> u128;
+
+    // returns false, leaving the set unchanged, if any of the values are already in the set
+    fn insert_interval(&mut self, lower: T, upper: T) -> bool;
+
+    // returns the number of values removed
+    fn remove_interval(&mut self, lower: T, upper: T) -> u128;
+
+    fn remove_first_value_in(&mut self, lower: T, upper: T) -> Option<T>;
+
+    fn contains_interval(&self, lower: T, upper: T) -> bool;
+
+    // the lowest value that starts a run of at least len values
+    fn find_interval_of_len(&self, len: u128) -> Option<T>;
+
+    // the nth lowest value, counting from 0
+    fn nth_value(&self, n: u128) -> Option<T> {
+        nth_value_of(self.to_intervals().iter(), n)
+    }
+
+    fn insert_value(&mut self, value: T) -> bool {
+        self.insert_interval(value, value)
+    }
+
+    fn remove_value(&mut self, value: T) -> bool {
+        self.remove_interval(value, value) == 1
+    }
+
+    fn remove_first_value(&mut self) -> Option<T> {
+        self.remove_first_value_in(T::MIN, T::MAX)
+    }
+
+    fn union(&mut self, other: &Intervals<T>) {
+        for interval in other.iter() {
+            self.remove_interval(interval.lower(), interval.upper());
+
+            self.insert_interval(interval.lower(), interval.upper());
+        }
+    }
+
+    fn dump(&self) -> String {
+        self.to_intervals().dump()
+    }
+
+    fn validate(&self) -> Result<(), ValidationReport<T>> {
+        self.to_intervals().validate()
+    }
+}
+
+fn nth_value_of<T: IdType>(intervals: impl Iterator<Item = Interval<T>>, mut n: u128) -> Option<T> {
+    for interval in intervals {
+        if n < interval.len() {
+            return Some(interval.lower() + T::from_u128(n));
+        }
+
+        n -= interval.len();
+    }
+
+    None
+}
+
+impl<T: IdType> FreeSet<T> for Intervals<T> {
+    fn from_intervals(intervals: &Intervals<T>) -> Self {
+        intervals.clone()
+    }
+
+    fn to_intervals(&self) -> Intervals<T> {
+        self.clone()
+    }
+
+    fn is_empty(&self) -> bool {
+        Intervals::is_empty(self)
+    }
+
+    fn value_count(&self) -> u128 {
+        Intervals::value_count(self)
+    }
+
+    fn interval_count(&self) -> usize {
+        Intervals::interval_count(self)
+    }
+
+    fn largest_interval_len(&self) -> u128 {
+        Intervals::largest_interval_len(self)
+    }
+
+    fn insert_interval(&mut self, lower: T, upper: T) -> bool {
+        Intervals::insert_interval(self, lower, upper)
+    }
+
+    fn remove_interval(&mut self, lower: T, upper: T) -> u128 {
+        Intervals::remove_interval(self, lower, upper)
+    }
+
+    fn remove_first_value_in(&mut self, lower: T, upper: T) -> Option<T> {
+        Intervals::remove_first_value_in(self, lower, upper)
+    }
+
+    fn contains_interval(&self, lower: T, upper: T) -> bool {
+        Intervals::contains_interval(self, lower, upper)
+    }
+
+    fn find_interval_of_len(&self, len: u128) -> Option<T> {
+        self.iter().find(|interval| interval.len() >= len).map(|interval| interval.lower())
+    }
+
+    fn nth_value(&self, n: u128) -> Option<T> {
+        nth_value_of(self.iter(), n)
+    }
+
+    fn insert_value(&mut self, value: T) -> bool {
+        Intervals::insert_value(self, value)
+    }
+
+    fn remove_value(&mut self, value: T) -> bool {
+        Intervals::remove_value(self, value)
+    }
+
+    fn remove_first_value(&mut self) -> Option<T> {
+        if Intervals::is_empty(self) {
+            return None;
+        }
+
+        Some(Intervals::remove_first_value(self))
+    }
+
+    fn union(&mut self, other: &Intervals<T>) {
+        Intervals::union(self, other)
+    }
+
+    fn dump(&self) -> String {
+        Intervals::dump(self)
+    }
+
+    fn validate(&self) -> Result<(), ValidationReport<T>> {
+        Intervals::validate(self)
+    }
+}
@@ -0,0 +1,205 @@
+use std::mem;
+
+use crate::bitmap_free_set::{BitmapFreeSet, MAX_BITS};
+use crate::free_set::FreeSet;
+use crate::id_type::IdType;
+use crate::intervals::Intervals;
+
+// how often, in mutations, to reconsider the representation, counting the intervals in a
+// bitmap means scanning all of it
+const ADAPT_EVERY: u32 = 64;
+
+#[derive(Clone)]
+enum Representation<T: IdType> {
+    Intervals(Intervals<T>),
+    Bitmap(BitmapFreeSet<T>),
+}
+
+// Holds the free ids as Intervals whilst they are lightly fragmented and as a bitmap once
+// the intervals would take more than twice the memory of a bitmap covering them. It only
+// switches back once the intervals would take less than half of the bitmap's memory so that
+// it doesn't flip back and forth.
+
+#[derive(Clone)]
+pub struct HybridFreeSet<T: IdType> {
+    representation: Representation<T>,
+    mutations: u32,
+}
+
+macro_rules! with_free_set {
+    ($representation:expr, $free_set:ident => $body:expr) => {
+        match $representation {
+            Representation::Intervals($free_set) => $body,
+            Representation::Bitmap($free_set) => $body,
+        }
+    };
+}
+
+impl<T: IdType> HybridFreeSet<T> {
+    pub fn is_bitmap(&self) -> bool {
+        matches!(self.representation, Representation::Bitmap(_))
+    }
+
+    fn mutated(&mut self) {
+        self.mutations += 1;
+
+        if self.mutations >= ADAPT_EVERY {
+            self.mutations = 0;
+
+            self.adapt();
+        }
+    }
+
+    fn adapt(&mut self) {
+        let interval_bytes = self.interval_count() as u128 * 2 * mem::size_of::<T>() as u128;
+
+        match &self.representation {
+            Representation::Intervals(intervals) => {
+                let span = match (intervals.iter().next(), intervals.iter().next_back()) {
+                    (Some(first), Some(last)) => (last.upper().to_u128() - first.lower().to_u128()).saturating_add(1),
+                    _ => return,
+                };
+
+                if span <= MAX_BITS / 2 && interval_bytes > span / 8 * 2 {
+                    self.representation = Representation::Bitmap(BitmapFreeSet::from_intervals(intervals));
+                }
+            }
+            Representation::Bitmap(bitmap) => {
+                let intervals = bitmap.to_intervals();
+
+                let span = match (intervals.iter().next(), intervals.iter().next_back()) {
+                    (Some(first), Some(last)) => last.upper().to_u128() - first.lower().to_u128() + 1,
+                    _ => 0,
+                };
+
+                if interval_bytes * 2 < span / 8 {
+                    self.representation = Representation::Intervals(intervals);
+                }
+            }
+        }
+    }
+}
+
+impl<T: IdType> FreeSet<T> for HybridFreeSet<T> {
+    fn from_intervals(intervals: &Intervals<T>) -> Self {
+        let mut hybrid = HybridFreeSet { representation: Representation::Intervals(intervals.clone()), mutations: 0 };
+
+        hybrid.adapt();
+
+        hybrid
+    }
+
+    fn to_intervals(&self) -> Intervals<T> {
+        with_free_set!(&self.representation, free_set => free_set.to_intervals())
+    }
+
+    fn is_empty(&self) -> bool {
+        with_free_set!(&self.representation, free_set => FreeSet::is_empty(free_set))
+    }
+
+    fn value_count(&self) -> u128 {
+        with_free_set!(&self.representation, free_set => FreeSet::value_count(free_set))
+    }
+
+    fn interval_count(&self) -> usize {
+        with_free_set!(&self.representation, free_set => FreeSet::interval_count(free_set))
+    }
+
+    fn largest_interval_len(&self) -> u128 {
+        with_free_set!(&self.representation, free_set => FreeSet::largest_interval_len(free_set))
+    }
+
+    fn insert_interval(&mut self, lower: T, upper: T) -> bool {
+        let inserted = with_free_set!(&mut self.representation, free_set => FreeSet::insert_interval(free_set, lower, upper));
+
+        self.mutated();
+
+        inserted
+    }
+
+    fn remove_interval(&mut self, lower: T, upper: T) -> u128 {
+        let removed = with_free_set!(&mut self.representation, free_set => FreeSet::remove_interval(free_set, lower, upper));
+
+        self.mutated();
+
+        removed
+    }
+
+    fn remove_first_value_in(&mut self, lower: T, upper: T) -> Option<T> {
+        let value = with_free_set!(&mut self.representation, free_set => FreeSet::remove_first_value_in(free_set, lower, upper));
+
+        self.mutated();
+
+        value
+    }
+
+    fn contains_interval(&self, lower: T, upper: T) -> bool {
+        with_free_set!(&self.representation, free_set => FreeSet::contains_interval(free_set, lower, upper))
+    }
+
+    fn find_interval_of_len(&self, len: u128) -> Option<T> {
+        with_free_set!(&self.representation, free_set => free_set.find_interval_of_len(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switches_to_bitmap_when_fragmented() {
+        let mut intervals = Intervals::<u16>::new();
+
+        intervals.insert_interval(0, 4095);
+
+        let mut hybrid = HybridFreeSet::from_intervals(&intervals);
+
+        assert_eq!(hybrid.is_bitmap(), false);
+
+        for id in (0..4096).step_by(2) {
+            assert_eq!(hybrid.remove_value(id), true);
+        }
+
+        assert_eq!(hybrid.is_bitmap(), true);
+
+        assert_eq!(hybrid.value_count(), 2048);
+        assert_eq!(hybrid.interval_count(), 2048);
+        assert_eq!(hybrid.remove_first_value(), Some(1));
+    }
+
+    #[test]
+    fn test_switches_back_to_intervals_when_defragmented() {
+        let mut intervals = Intervals::<u16>::new();
+
+        for id in (1..4096).step_by(2) {
+            intervals.insert_value(id);
+        }
+
+        let mut hybrid = HybridFreeSet::from_intervals(&intervals);
+
+        assert_eq!(hybrid.is_bitmap(), true);
+
+        for id in (0..4096).step_by(2) {
+            assert_eq!(hybrid.insert_value(id), true);
+        }
+
+        assert_eq!(hybrid.is_bitmap(), false);
+
+        assert_eq!(hybrid.dump(), "[0,4095]");
+    }
+
+    #[test]
+    fn test_large_ranges_stay_as_intervals() {
+        let mut intervals = Intervals::<u64>::new();
+
+        for id in (0..4096).step_by(2) {
+            intervals.insert_value(id);
+        }
+
+        intervals.insert_value(u64::MAX);
+
+        let hybrid = HybridFreeSet::from_intervals(&intervals);
+
+        assert_eq!(hybrid.is_bitmap(), false);
+    }
+}
@@ -1,28 +1,133 @@
+use std::collections::BTreeMap;
+
+use crate::free_set::FreeSet;
+use crate::id_manager_stats::IdManagerStats;
 use crate::id_type::IdType;
+use crate::interval::Interval;
 use crate::intervals::Intervals;
+use crate::parse_error::ParseError;
+use crate::range_error::RangeError;
+use crate::range_order::RangeOrder;
 use crate::reuse_policy::ReusePolicy;
+use crate::snapshot::IdManagerSnapshot;
+use crate::trace::{Trace, TraceOp};
 
-pub struct IdManager<T: IdType> {
-    free_ids: Intervals<T>,
+pub struct IdManager<T: IdType, S: FreeSet<T> = Intervals<T>> {
+    free_ids: S,
     reuse_policy: ReusePolicy,
+    range_order: RangeOrder,
+    ranges: Vec<Interval<T>>,           // in allocation order
+    next_range: usize,
     next_to_allocate: T,
-    min_id: T,
-    max_id: T,
+    wrap_arounds: u64,
+    allocated: u128,
+    peak_allocated: u128,
+    random_state: u64,                  // for ReuseRandom
+    reserved: BTreeMap<T, u64>,         // id -> when it was reserved
+    aborted: BTreeMap<u64, T>,          // when it was reserved -> id
+    aborted_ids: BTreeMap<T, u64>,      // id -> when it was reserved, for aborted ids
+    next_reservation: u64,
+    retired: u128,                      // ids left in the ranges by retire_interval()
+    trace: Option<Trace<T>>,
 }
 
-impl<T: IdType> IdManager<T> {
+impl<T: IdType, S: FreeSet<T>> IdManager<T, S> {
     pub fn new(reuse_policy: ReusePolicy) -> Self {
         Self::new_limited_range(reuse_policy, T::MIN, T::MAX)
     }
 
     pub fn new_limited_range(reuse_policy: ReusePolicy, min_id: T, max_id: T) -> Self {
-        let mut manager = IdManager::<T> { free_ids: Intervals::<T>::new(), reuse_policy, next_to_allocate: min_id, min_id, max_id };
+        let mut ranges = Intervals::<T>::new();
+
+        ranges.insert_interval(min_id, max_id);
+
+        Self::new_with_ranges(reuse_policy, ranges, RangeOrder::Ascending)
+    }
 
-        manager.free_ids.insert_interval(min_id, max_id);
+    pub fn new_with_ranges(reuse_policy: ReusePolicy, ranges: Intervals<T>, range_order: RangeOrder) -> Self {
+        if ranges.is_empty() {
+            panic!("ranges must not be empty");
+        }
+
+        let mut manager = IdManager {
+            free_ids: S::from_intervals(&ranges),
+            reuse_policy,
+            range_order,
+            ranges: Vec::new(),
+            next_range: 0,
+            next_to_allocate: T::MIN,
+            wrap_arounds: 0,
+            allocated: 0,
+            peak_allocated: 0,
+            random_state: match reuse_policy {
+                ReusePolicy::ReuseRandom(seed) => seed,
+                _ => 0,
+            },
+            reserved: BTreeMap::new(),
+            aborted: BTreeMap::new(),
+            aborted_ids: BTreeMap::new(),
+            next_reservation: 0,
+            retired: 0,
+            trace: None,
+        };
+
+        manager.set_ranges(&ranges);
+
+        manager.next_range = 0;
+        manager.next_to_allocate = manager.ranges[0].lower();
+
+        manager.check_invariants();
 
         manager
     }
 
+    pub fn from_snapshot(snapshot: IdManagerSnapshot<T>) -> Result<Self, ParseError> {
+        snapshot.validate()?;
+
+        let mut manager = Self::new_with_ranges(snapshot.reuse_policy, snapshot.ranges.clone(), snapshot.range_order);
+
+        manager.next_to_allocate = snapshot.next_to_allocate;
+
+        manager.set_ranges(&snapshot.ranges);
+
+        manager.allocated = snapshot.ranges.value_count() - snapshot.free_ids.value_count();
+
+        manager.peak_allocated = manager.allocated;
+
+        manager.free_ids = S::from_intervals(&snapshot.free_ids);
+
+        manager.check_invariants();
+
+        Ok(manager)
+    }
+
+    pub fn snapshot(&self) -> IdManagerSnapshot<T> {
+        // reserved ids are restored as allocated and aborted ids lose their place at the front
+        // of the free ids, as the snapshot only records which ids are free. Ids that were
+        // forgotten by remove_range() aren't in the ranges so they can't be restored either, and
+        // those retired by retire_interval() are restored as allocated.
+
+        // ReuseRandom holds the current state of the random number generator rather than the
+        // original seed so that a manager restored from the snapshot allocates the same ids
+
+        let reuse_policy = match self.reuse_policy {
+            ReusePolicy::ReuseRandom(_) => ReusePolicy::ReuseRandom(self.random_state),
+            reuse_policy => reuse_policy,
+        };
+
+        IdManagerSnapshot {
+            reuse_policy,
+            range_order: self.range_order,
+            ranges: self.ranges_as_intervals(),
+            next_to_allocate: self.next_to_allocate,
+            free_ids: self.free_ids.to_intervals(),
+        }
+    }
+
+    pub fn reuse_policy(&self) -> ReusePolicy {
+        self.reuse_policy
+    }
+
     pub fn dump(&self) -> String {
         self.free_ids.dump()
     }
@@ -31,15 +136,126 @@ impl<T: IdType> IdManager<T> {
         !self.free_ids.is_empty()
     }
 
+    pub fn wrap_arounds(&self) -> u64 {
+        self.wrap_arounds
+    }
+
+    pub fn stats(&self) -> IdManagerStats {
+        IdManagerStats {
+            capacity: self.capacity(),
+            allocated: self.allocated,
+            reserved: self.reserved.len() as u128,
+            peak_allocated: self.peak_allocated,
+            free_intervals: self.free_ids.interval_count(),
+            largest_free_run: self.free_ids.largest_interval_len(),
+            wrap_arounds: self.wrap_arounds,
+        }
+    }
+
+    // cheaper than stats() which has to look at every free interval
+
+    pub(crate) fn capacity(&self) -> u128 {
+        let capacity = self.ranges.iter().fold(0, |capacity: u128, range| capacity.saturating_add(range.len()));
+
+        capacity.saturating_sub(self.retired)
+    }
+
+    pub(crate) fn available(&self) -> u128 {
+        self.capacity() - self.allocated - self.reserved.len() as u128
+    }
+
+    pub fn reset_peak_allocated(&mut self) {
+        self.peak_allocated = self.allocated;
+    }
+
+    // records every operation that changes the manager, along with its result, until
+    // take_trace() is called
+
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Trace::new(self.snapshot()));
+    }
+
+    pub fn take_trace(&mut self) -> Option<Trace<T>> {
+        self.trace.take()
+    }
+
+    fn trace(&mut self, op: TraceOp<T>) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.record(op);
+        }
+    }
+
+    fn record_allocated(&mut self, count: u128) {
+        // saturates, as the capacity does, when every u128 is allocated
+
+        self.allocated = self.allocated.saturating_add(count);
+
+        self.peak_allocated = self.peak_allocated.max(self.allocated);
+    }
+
+    // aborted ids that are taken some other way lose their place at the front of the free ids,
+    // otherwise they would jump ahead of everything else if they were freed later
+
+    fn forget_aborted(&mut self, lower: T, upper: T) {
+        let taken: Vec<(T, u64)> = self.aborted_ids.range(lower..=upper).map(|(id, reservation)| (*id, *reservation)).collect();
+
+        for (id, reservation) in taken {
+            self.aborted_ids.remove(&id);
+            self.aborted.remove(&reservation);
+        }
+    }
+
     pub fn allocate(&mut self) -> T {
+        let id = self.take_next_id();
+
+        self.record_allocated(1);
+
+        self.trace(TraceOp::Allocate(id));
+
+        self.check_invariants();
+
+        id
+    }
+
+    fn take_next_id(&mut self) -> T {
         if self.free_ids.is_empty()
         {
             panic!("No Ids available")
         }
 
+        // aborted reservations are handed out again first, in the order that they were
+        // reserved, so that the ids allocated are the same as if they had never been reserved
+
+        if let Some((_, id)) = self.aborted.pop_first() {
+            self.aborted_ids.remove(&id);
+            self.free_ids.remove_value(id);
+
+            return id;
+        }
+
+        if let ReusePolicy::ReuseRandom(_) = self.reuse_policy {
+            // the range order doesn't apply as any free id can be chosen
+
+            let n = next_random(&mut self.random_state, self.free_ids.value_count());
+
+            let id = self.free_ids.nth_value(n).unwrap();
+
+            self.free_ids.remove_value(id);
+
+            return id;
+        }
+
         if self.reuse_policy == ReusePolicy::ReuseFast
         {
-            return self.free_ids.remove_first_value();
+            if self.range_order == RangeOrder::Ascending {
+                return self.free_ids.remove_first_value().unwrap();
+            }
+
+            for range in self.ranges.iter() {
+                if let Some(id) = self.free_ids.remove_first_value_in(range.lower(), range.upper()) {
+                    return id;
+                }
+            }
         }
 
         let id: T;
@@ -60,318 +276,1451 @@ impl<T: IdType> IdManager<T> {
         id
     }
 
-    pub fn mark_value_as_used(&mut self, id: T) {
-        if id < self.min_id && id > self.max_id {
-            panic!("id out of range");
-        }
+    // takes an id that doesn't count as allocated until it is committed, or that is returned
+    // to the front of the free ids if it is aborted
+
+    pub fn reserve(&mut self) -> T {
+        let id = self.take_next_id();
+
+        self.reserved.insert(id, self.next_reservation);
 
-        self.free_ids.remove_value(id);
+        self.next_reservation += 1;
+
+        self.trace(TraceOp::Reserve(id));
+
+        self.check_invariants();
+
+        id
     }
 
-    pub fn mark_interval_as_used(&mut self, lower: T, upper: T) {
-        if lower < self.min_id && lower > self.max_id {
-            panic!("lower id out of range");
+    pub fn commit(&mut self, id: T) {
+        if self.reserved.remove(&id).is_none() {
+            panic!("id is not reserved");
         }
 
-        if upper < self.min_id && upper > self.max_id {
-            panic!("upper id out of range");
-        }
+        self.record_allocated(1);
 
-        self.free_ids.remove_interval(lower, upper);
+        self.trace(TraceOp::Commit(id));
+
+        self.check_invariants();
     }
 
-    fn increment_id(&self, mut id: T) -> T {
-        if id == self.max_id
-        {
-            id = self.min_id;
-        } else {
-            id = id + T::one();
-            //id += T::one();           // needs additional bounds
-        }
+    pub fn abort(&mut self, id: T) {
+        let Some(reservation) = self.reserved.remove(&id) else {
+            panic!("id is not reserved");
+        };
 
-        id
+        self.free_ids.insert_value(id);
+
+        self.aborted.insert(reservation, id);
+        self.aborted_ids.insert(id, reservation);
+
+        self.trace(TraceOp::Abort(id));
+
+        self.check_invariants();
     }
 
-    pub fn free(&mut self, id: T) {
-        if !self.free_ids.insert_value(id)
-        {
-            panic!("id is not currently allocated");
+    pub fn allocate_block(&mut self, size: u128) -> Option<(T, T)> {
+        if size == 0 {
+            panic!("size must be > 0");
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::reuse_policy::ReusePolicy::ReuseFast;
-    use crate::reuse_policy::ReusePolicy::ReuseSlow;
+        let Some(lower) = self.free_ids.find_interval_of_len(size) else {
+            self.trace(TraceOp::AllocateBlock(size, None));
 
-    use super::*;
+            return None;
+        };
 
-    #[test]
-    fn test_new() {
-        let manager = IdManager::<u8>::new(ReuseFast);
+        let upper = lower + T::from_u128(size - 1);
 
-        assert_eq!(manager.dump(), "[0,255]");
+        self.free_ids.remove_interval(lower, upper);
+        self.forget_aborted(lower, upper);
+
+        self.record_allocated(size);
+
+        self.trace(TraceOp::AllocateBlock(size, Some((lower, upper))));
+
+        self.check_invariants();
+
+        Some((lower, upper))
     }
 
-    #[test]
-    fn test_new_for_all_supported_types() {
-        {
-            let manager = IdManager::<u8>::new(ReuseFast);
+    pub fn free_block(&mut self, lower: T, upper: T) {
+        // a block can't span a gap between ranges as the ids in the gap were never allocated
 
-            assert_eq!(manager.dump(), "[0,255]");
+        if upper < lower || !self.ranges.iter().any(|range| range.lower() <= lower && upper <= range.upper()) {
+            panic!("block out of range");
         }
-        {
-            let manager = IdManager::<u16>::new(ReuseFast);
 
-            assert_eq!(manager.dump(), "[0,65535]");
+        if !self.free_ids.insert_interval(lower, upper) {
+            panic!("block is not currently allocated");
         }
-        {
-            let manager = IdManager::<u32>::new(ReuseFast);
 
-            assert_eq!(manager.dump(), "[0,4294967295]");
-        }
-        {
-            let manager = IdManager::<u64>::new(ReuseFast);
+        self.allocated -= Interval::new_unchecked(lower, upper).len();
 
-            assert_eq!(manager.dump(), "[0,18446744073709551615]");
-        }
-        {
-            let manager = IdManager::<u128>::new(ReuseFast);
+        self.trace(TraceOp::FreeBlock(lower, upper));
 
-            assert_eq!(manager.dump(), "[0,340282366920938463463374607431768211455]");
+        self.check_invariants();
+    }
+
+    pub fn mark_value_as_used(&mut self, id: T) {
+        if !self.contains(id) {
+            panic!("id out of range");
         }
-        {
-            let manager = IdManager::<usize>::new(ReuseFast);
 
-            assert_eq!(manager.dump(), "[0,18446744073709551615]");
+        if self.free_ids.remove_value(id) {
+            self.forget_aborted(id, id);
+            self.record_allocated(1);
         }
-    }
 
-    #[test]
-    fn test_new_limited_range() {
-        let manager = IdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+        self.trace(TraceOp::MarkAsUsed(id, id));
 
-        assert_eq!(manager.dump(), "[10,50]");
+        self.check_invariants();
     }
 
-    #[test]
-    fn test_can_allocate() {
-        let manager = IdManager::<u8>::new(ReuseFast);
+    pub fn mark_interval_as_used(&mut self, lower: T, upper: T) {
+        if !self.contains(lower) {
+            panic!("lower id out of range");
+        }
 
-        assert_eq!(manager.can_allocate(), true);
-    }
+        if !self.contains(upper) {
+            panic!("upper id out of range");
+        }
 
-    #[test]
-    fn test_allocate() {
-        let mut manager = IdManager::<u8>::new(ReuseFast);
+        // as with free_block(), ids in a gap between ranges can't be marked
 
-        assert_eq!(manager.allocate(), 0);
+        if upper < lower || !self.ranges.iter().any(|range| range.lower() <= lower && upper <= range.upper()) {
+            panic!("interval out of range");
+        }
 
-        assert_eq!(manager.dump(), "[1,255]");
-    }
+        let removed = self.free_ids.remove_interval(lower, upper);
+        self.forget_aborted(lower, upper);
 
-    #[test]
-    fn test_allocate_all_ids_and_wrap() {
-        let mut manager = IdManager::<u8>::new(ReuseSlow);
+        self.record_allocated(removed);
 
-        for i in 0..u8::MAX {
-            assert_eq!(manager.allocate(), i);
-        }
-        assert_eq!(manager.dump(), "[255]");
+        self.trace(TraceOp::MarkAsUsed(lower, upper));
 
-        assert_eq!(manager.allocate(), 255);
+        self.check_invariants();
+    }
 
-        assert_eq!(manager.can_allocate(), false);
+    pub fn grow_range(&mut self, new_min_id: T, new_max_id: T) -> Result<(), RangeError> {
+        let min_id = self.min_id();
 
-        assert_eq!(manager.dump(), "");
+        let max_id = self.max_id();
 
-        for i in 0..10 {
-            manager.free(i);
+        if new_min_id > min_id || new_max_id < max_id {
+            return Err(RangeError::InvalidRange);
         }
 
-        assert_eq!(manager.dump(), "[0,9]");
+        let mut ranges = self.ranges_as_intervals();
 
-        for i in 0..10 {
-            assert_eq!(manager.allocate(), i);
+        if new_min_id < min_id {
+            self.free_ids.insert_interval(new_min_id, min_id - T::one());
+
+            ranges.insert_interval(new_min_id, min_id - T::one());
         }
-        assert_eq!(manager.dump(), "");
-    }
 
-    #[test]
-    fn test_allocate_all_ids_and_wrap_limited_range() {
-        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+        if new_max_id > max_id {
+            self.free_ids.insert_interval(max_id + T::one(), new_max_id);
 
-        for i in 10..50 {
-            assert_eq!(manager.allocate(), i);
+            ranges.insert_interval(max_id + T::one(), new_max_id);
         }
-        assert_eq!(manager.dump(), "[50]");
 
-        assert_eq!(manager.allocate(), 50);
+        self.set_ranges(&ranges);
 
-        assert_eq!(manager.can_allocate(), false);
+        self.trace(TraceOp::GrowRange(new_min_id, new_max_id));
 
-        assert_eq!(manager.dump(), "");
+        self.check_invariants();
 
-        for i in 10..20 {
-            manager.free(i);
-        }
+        Ok(())
+    }
 
-        assert_eq!(manager.dump(), "[10,19]");
+    pub fn add_range(&mut self, lower: T, upper: T) -> Result<(), RangeError> {
+        let mut ranges = self.ranges_as_intervals();
 
-        for i in 10..20 {
-            assert_eq!(manager.allocate(), i);
+        if !ranges.insert_interval(lower, upper) {
+            return Err(RangeError::InvalidRange);
         }
-        assert_eq!(manager.dump(), "");
-    }
 
-    #[test]
-    fn test_free() {
-        let mut manager = IdManager::<u8>::new(ReuseFast);
+        self.free_ids.insert_interval(lower, upper);
 
-        assert_eq!(manager.allocate(), 0);
+        self.set_ranges(&ranges);
 
-        assert_eq!(manager.dump(), "[1,255]");
+        self.trace(TraceOp::AddRange(lower, upper));
 
-        manager.free(0);
+        self.check_invariants();
 
-        assert_eq!(manager.dump(), "[0,255]");
+        Ok(())
     }
 
-    #[test]
-    #[should_panic(expected = "id is not currently allocated")]
-    fn test_free_id_not_allocated() {
-        let mut manager = IdManager::<u8>::new(ReuseFast);
+    pub fn shrink_range(&mut self, new_min_id: T, new_max_id: T) -> Result<(), RangeError> {
+        let min_id = self.min_id();
 
-        assert_eq!(manager.dump(), "[0,255]");
+        let max_id = self.max_id();
 
-        manager.free(0);
+        if new_min_id < min_id || new_max_id > max_id || new_max_id < new_min_id {
+            return Err(RangeError::InvalidRange);
+        }
 
-        assert_eq!(manager.dump(), "[0,255]");
-    }
+        let mut ranges = self.ranges_as_intervals();
 
-    #[test]
-    fn test_reuse_fast() {
-        let mut manager = IdManager::<u8>::new(ReuseFast);
+        let mut removed = Vec::new();
 
-        for i in 0..10 {
-            assert_eq!(manager.allocate(), i);
+        if new_min_id > min_id {
+            removed.push((min_id, new_min_id - T::one()));
         }
 
-        assert_eq!(manager.dump(), "[10,255]");
+        if new_max_id < max_id {
+            removed.push((new_max_id + T::one(), max_id));
+        }
 
-        manager.free(2);
-        manager.free(6);
-        manager.free(7);
-        manager.free(4);
+        for (lower, upper) in removed.iter() {
+            ranges.remove_interval(*lower, *upper);
+        }
 
-        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+        if ranges.is_empty() {
+            return Err(RangeError::InvalidRange);
+        }
 
-        assert_eq!(manager.allocate(), 2);
-        assert_eq!(manager.allocate(), 4);
-        assert_eq!(manager.allocate(), 6);
-        assert_eq!(manager.allocate(), 7);
-        assert_eq!(manager.allocate(), 10);
+        for range in self.ranges.iter() {
+            for (lower, upper) in removed.iter() {
+                let lower = range.lower().max(*lower);
 
-        assert_eq!(manager.dump(), "[11,255]");
-    }
+                let upper = range.upper().min(*upper);
 
-    #[test]
-    fn test_reuse_slow() {
-        let mut manager = IdManager::<u8>::new(ReuseSlow);
+                if lower <= upper && !self.free_ids.contains_interval(lower, upper) {
+                    return Err(RangeError::IdsInUse);
+                }
+            }
+        }
 
-        for i in 0..10 {
-            assert_eq!(manager.allocate(), i);
+        for (lower, upper) in removed.iter() {
+            self.free_ids.remove_interval(*lower, *upper);
+            self.forget_aborted(*lower, *upper);
         }
 
-        assert_eq!(manager.dump(), "[10,255]");
+        self.set_ranges(&ranges);
 
-        manager.free(2);
-        manager.free(6);
-        manager.free(7);
-        manager.free(4);
+        self.trace(TraceOp::ShrinkRange(new_min_id, new_max_id));
 
-        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+        self.check_invariants();
 
-        for i in 10..255 {
-            assert_eq!(manager.allocate(), i);
-        }
+        Ok(())
+    }
 
-        assert_eq!(manager.dump(), "[2], [4], [6,7], [255]");
+    // drops ids from the ranges even if some of them are allocated, those ids are forgotten
+    // and must not be freed. The ranges can't be left empty.
 
-        assert_eq!(manager.allocate(), 255);
+    pub fn remove_range(&mut self, lower: T, upper: T) -> Result<(), RangeError> {
+        let mut ranges = self.ranges_as_intervals();
 
-        assert_eq!(manager.allocate(), 2);
-        assert_eq!(manager.allocate(), 4);
-        assert_eq!(manager.allocate(), 6);
-        assert_eq!(manager.allocate(), 7);
+        if upper < lower || !ranges.contains_interval(lower, upper) {
+            return Err(RangeError::InvalidRange);
+        }
 
-        assert_eq!(manager.dump(), "");
+        ranges.remove_interval(lower, upper);
+
+        if ranges.is_empty() {
+            return Err(RangeError::InvalidRange);
+        }
+
+        if self.reserved.range(lower..=upper).next().is_some() {
+            return Err(RangeError::IdsInUse);
+        }
+
+        let free = self.free_ids.remove_interval(lower, upper);
+        self.forget_aborted(lower, upper);
+
+        self.allocated -= Interval::new_unchecked(lower, upper).len() - free;
+
+        self.set_ranges(&ranges);
+
+        self.trace(TraceOp::RemoveRange(lower, upper));
+
+        self.check_invariants();
+
+        Ok(())
+    }
+
+    // forgets every id in [lower, upper], even those that are allocated or reserved, e.g. when
+    // a block leased from a parent is lost. The interval is removed from the ranges unless
+    // they would be left empty, when its ids are left there but no longer count towards the
+    // capacity.
+
+    pub(crate) fn retire_interval(&mut self, lower: T, upper: T) {
+        let mut ranges = self.ranges_as_intervals();
+
+        if upper < lower || !ranges.contains_interval(lower, upper) {
+            panic!("interval out of range");
+        }
+
+        let free = self.free_ids.remove_interval(lower, upper);
+        self.forget_aborted(lower, upper);
+
+        let reserved: Vec<T> = self.reserved.range(lower..=upper).map(|(id, _)| *id).collect();
+
+        for id in reserved.iter() {
+            self.reserved.remove(id);
+        }
+
+        let len = Interval::new_unchecked(lower, upper).len();
+
+        self.allocated -= len - free - reserved.len() as u128;
+
+        ranges.remove_interval(lower, upper);
+
+        if ranges.is_empty() {
+            self.retired += len;
+        } else {
+            self.set_ranges(&ranges);
+        }
+
+        self.trace(TraceOp::Retire(lower, upper));
+
+        self.check_invariants();
+    }
+
+    fn min_id(&self) -> T {
+        self.ranges.iter().map(|range| range.lower()).min().unwrap()
+    }
+
+    fn max_id(&self) -> T {
+        self.ranges.iter().map(|range| range.upper()).max().unwrap()
+    }
+
+    fn contains(&self, id: T) -> bool {
+        self.ranges.iter().any(|range| range.contains_value(id))
+    }
+
+    fn ranges_as_intervals(&self) -> Intervals<T> {
+        let mut ranges = Intervals::<T>::new();
+
+        for range in self.ranges.iter() {
+            ranges.insert_interval(range.lower(), range.upper());
+        }
+
+        ranges
+    }
+
+    fn set_ranges(&mut self, ranges: &Intervals<T>) {
+        self.ranges = ranges.iter().collect();
+
+        if self.range_order == RangeOrder::Descending {
+            self.ranges.reverse();
+        }
+
+        // keep allocating from where we were if we still can, otherwise start from the first range
+
+        match self.ranges.iter().position(|range| range.contains_value(self.next_to_allocate)) {
+            Some(index) => self.next_range = index,
+            None => {
+                self.next_range = 0;
+                self.next_to_allocate = self.ranges[0].lower();
+            }
+        }
+    }
+
+    fn increment_id(&mut self, mut id: T) -> T {
+        if id == self.ranges[self.next_range].upper()
+        {
+            self.next_range += 1;
+
+            if self.next_range == self.ranges.len() {
+                self.next_range = 0;
+
+                self.wrap_arounds += 1;
+            }
+
+            id = self.ranges[self.next_range].lower();
+        } else {
+            id = id + T::one();
+            //id += T::one();           // needs additional bounds
+        }
+
+        id
+    }
+
+    pub fn free(&mut self, id: T) {
+        if !self.contains(id) {
+            panic!("id out of range");
+        }
+
+        if self.reserved.contains_key(&id) {
+            panic!("id is reserved and must be committed or aborted");
+        }
+
+        if !self.free_ids.insert_value(id)
+        {
+            panic!("id is not currently allocated");
+        }
+
+        self.allocated -= 1;
+
+        self.trace(TraceOp::Free(id));
+
+        self.check_invariants();
+    }
+
+    #[cfg(feature = "validate")]
+    fn check_invariants(&self) {
+        if let Err(report) = self.free_ids.validate() {
+            panic!("free ids: {}", report);
+        }
+
+        for interval in self.free_ids.to_intervals().iter() {
+            if !self.ranges.iter().any(|range| range.lower() <= interval.lower() && interval.upper() <= range.upper()) {
+                panic!("free ids {} are outside of the ranges", interval);
+            }
+        }
+
+        let capacity = self.capacity();
+
+        // the counts saturate when the ranges cover every u128 so they can't be compared
+
+        let taken = self.allocated + self.reserved.len() as u128;
+
+        if capacity < u128::MAX && taken != capacity - self.free_ids.value_count() {
+            panic!("{} ids are recorded as allocated or reserved but {} are not free", taken, capacity - self.free_ids.value_count());
+        }
+
+        if !self.ranges[self.next_range].contains_value(self.next_to_allocate) {
+            panic!("next id to allocate {} is not in range {}", self.next_to_allocate, self.ranges[self.next_range]);
+        }
+
+        if self.aborted.len() != self.aborted_ids.len() {
+            panic!("{} aborted reservations but {} aborted ids", self.aborted.len(), self.aborted_ids.len());
+        }
+
+        for id in self.aborted.values() {
+            if !self.free_ids.contains_interval(*id, *id) {
+                panic!("aborted id {} is not free", id);
+            }
+        }
     }
 
+    #[cfg(not(feature = "validate"))]
+    fn check_invariants(&self) {}
+}
+
+// a number below bound from splitmix64, which is small and gives the same sequence on every
+// platform
+
+fn next_random(state: &mut u64, bound: u128) -> u128 {
+    let mut next = || {
+        *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = *state;
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        z ^ (z >> 31)
+    };
+
+    let random = if bound <= u64::MAX as u128 { next() as u128 } else { ((next() as u128) << 64) | next() as u128 };
+
+    random % bound
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reuse_policy::ReusePolicy::ReuseFast;
+    use crate::reuse_policy::ReusePolicy::ReuseSlow;
+    use crate::reuse_policy::ReusePolicy::ReuseRandom;
+    use crate::bitmap_free_set::BitmapFreeSet;
+
+    use super::*;
+
     #[test]
-    fn test_mark_value_as_used() {
-        let mut manager = IdManager::<u8>::new(ReuseSlow);
+    fn test_new() {
+        let manager = IdManager::<u8>::new(ReuseFast);
 
         assert_eq!(manager.dump(), "[0,255]");
+    }
 
-        manager.mark_value_as_used(0);
+    #[test]
+    fn test_new_for_all_supported_types() {
+        {
+            let manager = IdManager::<u8>::new(ReuseFast);
 
-        assert_eq!(manager.dump(), "[1,255]");
+            assert_eq!(manager.dump(), "[0,255]");
+        }
+        {
+            let manager = IdManager::<u16>::new(ReuseFast);
 
-        manager.mark_value_as_used(2);
+            assert_eq!(manager.dump(), "[0,65535]");
+        }
+        {
+            let manager = IdManager::<u32>::new(ReuseFast);
 
-        assert_eq!(manager.dump(), "[1], [3,255]");
+            assert_eq!(manager.dump(), "[0,4294967295]");
+        }
+        {
+            let manager = IdManager::<u64>::new(ReuseFast);
 
-        manager.mark_value_as_used(4);
+            assert_eq!(manager.dump(), "[0,18446744073709551615]");
+        }
+        {
+            let manager = IdManager::<u128>::new(ReuseFast);
 
-        assert_eq!(manager.dump(), "[1], [3], [5,255]");
+            assert_eq!(manager.dump(), "[0,340282366920938463463374607431768211455]");
+        }
+        {
+            let manager = IdManager::<usize>::new(ReuseFast);
 
-        manager.mark_value_as_used(10);
+            assert_eq!(manager.dump(), "[0,18446744073709551615]");
+        }
+    }
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,255]");
+    #[test]
+    fn test_new_limited_range() {
+        let manager = IdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
 
-        manager.mark_value_as_used(255);
+        assert_eq!(manager.dump(), "[10,50]");
+    }
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,254]");
+    #[test]
+    fn test_can_allocate() {
+        let manager = IdManager::<u8>::new(ReuseFast);
 
-        manager.mark_value_as_used(253);
+        assert_eq!(manager.can_allocate(), true);
+    }
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,252], [254]");
+    #[test]
+    fn test_allocate() {
+        let mut manager = IdManager::<u8>::new(ReuseFast);
 
-        manager.mark_value_as_used(251);
+        assert_eq!(manager.allocate(), 0);
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,250], [252], [254]");
+        assert_eq!(manager.dump(), "[1,255]");
     }
 
     #[test]
-    fn test_mark_interval_as_used() {
+    fn test_allocate_all_ids_and_wrap() {
         let mut manager = IdManager::<u8>::new(ReuseSlow);
 
-        assert_eq!(manager.dump(), "[0,255]");
+        for i in 0..u8::MAX {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "[255]");
 
-        manager.mark_interval_as_used(0, 1);
+        assert_eq!(manager.wrap_arounds(), 0);
 
-        assert_eq!(manager.dump(), "[2,255]");
+        assert_eq!(manager.allocate(), 255);
 
-        manager.mark_interval_as_used(4, 10);
+        assert_eq!(manager.wrap_arounds(), 1);
 
-        assert_eq!(manager.dump(), "[2,3], [11,255]");
+        assert_eq!(manager.can_allocate(), false);
 
-        manager.mark_interval_as_used(3, 12);
+        assert_eq!(manager.dump(), "");
 
-        assert_eq!(manager.dump(), "[2], [13,255]");
+        for i in 0..10 {
+            manager.free(i);
+        }
 
-        manager.mark_interval_as_used(254, 255);
+        assert_eq!(manager.dump(), "[0,9]");
 
-        assert_eq!(manager.dump(), "[2], [13,253]");
+        for i in 0..10 {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "");
+    }
 
-        manager.mark_interval_as_used(250, 251);
+    #[test]
+    fn test_allocate_all_ids_and_wrap_limited_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
 
-        assert_eq!(manager.dump(), "[2], [13,249], [252,253]");
+        for i in 10..50 {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "[50]");
 
-        manager.mark_interval_as_used(0, 255);
+        assert_eq!(manager.allocate(), 50);
+
+        assert_eq!(manager.can_allocate(), false);
+
+        assert_eq!(manager.dump(), "");
+
+        for i in 10..20 {
+            manager.free(i);
+        }
+
+        assert_eq!(manager.dump(), "[10,19]");
 
+        for i in 10..20 {
+            assert_eq!(manager.allocate(), i);
+        }
         assert_eq!(manager.dump(), "");
     }
+
+    #[test]
+    fn test_free() {
+        let mut manager = IdManager::<u8>::new(ReuseFast);
+
+        assert_eq!(manager.allocate(), 0);
+
+        assert_eq!(manager.dump(), "[1,255]");
+
+        manager.free(0);
+
+        assert_eq!(manager.dump(), "[0,255]");
+    }
+
+    #[test]
+    #[should_panic(expected = "id is not currently allocated")]
+    fn test_free_id_not_allocated() {
+        let mut manager = IdManager::<u8>::new(ReuseFast);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        manager.free(0);
+
+        assert_eq!(manager.dump(), "[0,255]");
+    }
+
+    #[test]
+    fn test_reuse_fast() {
+        let mut manager = IdManager::<u8>::new(ReuseFast);
+
+        for i in 0..10 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.dump(), "[10,255]");
+
+        manager.free(2);
+        manager.free(6);
+        manager.free(7);
+        manager.free(4);
+
+        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+
+        assert_eq!(manager.allocate(), 2);
+        assert_eq!(manager.allocate(), 4);
+        assert_eq!(manager.allocate(), 6);
+        assert_eq!(manager.allocate(), 7);
+        assert_eq!(manager.allocate(), 10);
+
+        assert_eq!(manager.dump(), "[11,255]");
+    }
+
+    #[test]
+    fn test_reuse_slow() {
+        let mut manager = IdManager::<u8>::new(ReuseSlow);
+
+        for i in 0..10 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.dump(), "[10,255]");
+
+        manager.free(2);
+        manager.free(6);
+        manager.free(7);
+        manager.free(4);
+
+        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+
+        for i in 10..255 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.dump(), "[2], [4], [6,7], [255]");
+
+        assert_eq!(manager.allocate(), 255);
+
+        assert_eq!(manager.allocate(), 2);
+        assert_eq!(manager.allocate(), 4);
+        assert_eq!(manager.allocate(), 6);
+        assert_eq!(manager.allocate(), 7);
+
+        assert_eq!(manager.dump(), "");
+    }
+
+    fn allocate_all<S: FreeSet<u8>>(manager: &mut IdManager<u8, S>) -> Vec<u8> {
+        let mut ids = Vec::new();
+
+        while manager.can_allocate() {
+            ids.push(manager.allocate());
+        }
+
+        ids
+    }
+
+    #[test]
+    fn test_reuse_random() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseRandom(42), 10, 50);
+
+        let ids = allocate_all(&mut manager);
+
+        assert_eq!(ids.len(), 41);
+
+        let mut sorted = ids.clone();
+
+        sorted.sort();
+
+        assert_eq!(sorted, (10..=50).collect::<Vec<u8>>());
+        assert_ne!(ids, sorted);
+
+        // the same seed gives the same ids whichever free set is used
+
+        assert_eq!(allocate_all(&mut IdManager::<u8>::new_limited_range(ReuseRandom(42), 10, 50)), ids);
+        assert_eq!(allocate_all(&mut IdManager::<u8, BitmapFreeSet<u8>>::new_limited_range(ReuseRandom(42), 10, 50)), ids);
+
+        assert_ne!(allocate_all(&mut IdManager::<u8>::new_limited_range(ReuseRandom(43), 10, 50)), ids);
+    }
+
+    #[test]
+    fn test_reuse_random_reuses_freed_ids() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseRandom(7), 10, 12);
+
+        let ids = allocate_all(&mut manager);
+
+        manager.free(ids[1]);
+
+        assert_eq!(manager.allocate(), ids[1]);
+        assert_eq!(manager.can_allocate(), false);
+    }
+
+    #[test]
+    fn test_reuse_random_snapshot() {
+        let mut manager = IdManager::<u64>::new(ReuseRandom(1));
+
+        manager.allocate();
+
+        let snapshot = manager.snapshot();
+
+        assert_ne!(snapshot.reuse_policy, ReuseRandom(1));
+
+        let mut restored = IdManager::<u64>::from_snapshot(snapshot.to_string().parse().unwrap()).unwrap();
+
+        for _ in 0..100 {
+            assert_eq!(restored.allocate(), manager.allocate());
+        }
+
+        assert_eq!(manager.reuse_policy(), ReuseRandom(1));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        assert_eq!(manager.reserve(), 10);
+
+        let stats = manager.stats();
+
+        assert_eq!(stats.allocated, 0);
+        assert_eq!(stats.reserved, 1);
+        assert_eq!(stats.available(), 10);
+        assert_eq!(manager.dump(), "[11,20]");
+
+        manager.commit(10);
+
+        let stats = manager.stats();
+
+        assert_eq!(stats.allocated, 1);
+        assert_eq!(stats.reserved, 0);
+        assert_eq!(manager.dump(), "[11,20]");
+    }
+
+    #[test]
+    fn test_abort_keeps_reuse_slow_order() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        assert_eq!(manager.reserve(), 10);
+        assert_eq!(manager.reserve(), 11);
+        assert_eq!(manager.allocate(), 12);
+
+        manager.abort(11);
+        manager.abort(10);
+
+        assert_eq!(manager.dump(), "[10,11], [13,20]");
+        assert_eq!(manager.stats().reserved, 0);
+
+        // as if 10 and 11 had never been reserved
+
+        assert_eq!(manager.allocate(), 10);
+        assert_eq!(manager.allocate(), 11);
+        assert_eq!(manager.allocate(), 13);
+
+        // an aborted id that has since been taken is skipped
+
+        assert_eq!(manager.reserve(), 14);
+
+        manager.abort(14);
+        manager.mark_value_as_used(14);
+
+        assert_eq!(manager.allocate(), 15);
+    }
+
+    #[test]
+    fn test_aborted_id_taken_and_freed_keeps_reuse_slow_order() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        assert_eq!(manager.reserve(), 10);
+        assert_eq!(manager.reserve(), 11);
+
+        manager.abort(10);
+        manager.mark_value_as_used(10);
+
+        assert_eq!(manager.allocate(), 12);
+
+        // 10 is freed like any other id so it goes to the back of the free ids
+
+        manager.free(10);
+
+        assert_eq!(manager.allocate(), 13);
+
+        manager.commit(11);
+
+        assert_eq!(manager.allocate(), 14);
+    }
+
+    #[test]
+    fn test_aborted_ids_taken_by_block_and_shrink_are_forgotten() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        assert_eq!(manager.reserve(), 10);
+        assert_eq!(manager.reserve(), 11);
+        assert_eq!(manager.reserve(), 12);
+
+        manager.abort(10);
+        manager.abort(12);
+
+        // 10 is the only free block of one id before 13
+
+        assert_eq!(manager.allocate_block(1), Some((10, 10)));
+
+        manager.commit(11);
+        manager.shrink_range(13, 20).unwrap_err();
+
+        manager.free(10);
+        manager.free(11);
+        manager.shrink_range(13, 20).unwrap();
+
+        assert_eq!(manager.allocate(), 13);
+    }
+
+    #[test]
+    #[should_panic(expected = "id is not reserved")]
+    fn test_commit_not_reserved() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let id = manager.allocate();
+
+        manager.commit(id);
+    }
+
+    #[test]
+    #[should_panic(expected = "id is not reserved")]
+    fn test_abort_twice() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let id = manager.reserve();
+
+        manager.abort(id);
+        manager.abort(id);
+    }
+
+    #[test]
+    #[should_panic(expected = "id is reserved and must be committed or aborted")]
+    fn test_free_reserved() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let id = manager.reserve();
+
+        manager.free(id);
+    }
+
+    #[test]
+    fn test_mark_value_as_used() {
+        let mut manager = IdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        manager.mark_value_as_used(0);
+
+        assert_eq!(manager.dump(), "[1,255]");
+
+        manager.mark_value_as_used(2);
+
+        assert_eq!(manager.dump(), "[1], [3,255]");
+
+        manager.mark_value_as_used(4);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,255]");
+
+        manager.mark_value_as_used(10);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,255]");
+
+        manager.mark_value_as_used(255);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,254]");
+
+        manager.mark_value_as_used(253);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,252], [254]");
+
+        manager.mark_value_as_used(251);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,250], [252], [254]");
+    }
+
+    #[test]
+    fn test_mark_interval_as_used() {
+        let mut manager = IdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        manager.mark_interval_as_used(0, 1);
+
+        assert_eq!(manager.dump(), "[2,255]");
+
+        manager.mark_interval_as_used(4, 10);
+
+        assert_eq!(manager.dump(), "[2,3], [11,255]");
+
+        manager.mark_interval_as_used(3, 12);
+
+        assert_eq!(manager.dump(), "[2], [13,255]");
+
+        manager.mark_interval_as_used(254, 255);
+
+        assert_eq!(manager.dump(), "[2], [13,253]");
+
+        manager.mark_interval_as_used(250, 251);
+
+        assert_eq!(manager.dump(), "[2], [13,249], [252,253]");
+
+        manager.mark_interval_as_used(0, 255);
+
+        assert_eq!(manager.dump(), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "id out of range")]
+    fn test_free_id_out_of_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+
+        manager.free(51);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        assert_eq!(manager.stats(), IdManagerStats {
+            capacity: 41,
+            allocated: 0,
+            reserved: 0,
+            peak_allocated: 0,
+            free_intervals: 1,
+            largest_free_run: 41,
+            wrap_arounds: 0 });
+
+        for i in 10..20 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        manager.mark_interval_as_used(30, 39);
+
+        manager.mark_value_as_used(45);
+
+        assert_eq!(manager.dump(), "[20,29], [40,44], [46,50]");
+
+        assert_eq!(manager.stats(), IdManagerStats {
+            capacity: 41,
+            allocated: 21,
+            reserved: 0,
+            peak_allocated: 21,
+            free_intervals: 3,
+            largest_free_run: 10,
+            wrap_arounds: 0 });
+
+        for i in 10..15 {
+            manager.free(i);
+        }
+
+        let stats = manager.stats();
+
+        assert_eq!(stats.allocated, 16);
+        assert_eq!(stats.peak_allocated, 21);
+        assert_eq!(stats.available(), 25);
+        assert_eq!(stats.free_intervals, 4);
+
+        manager.reset_peak_allocated();
+
+        assert_eq!(manager.stats().peak_allocated, 16);
+    }
+
+    #[test]
+    fn test_stats_marking_already_used_ids() {
+        let mut manager = IdManager::<u8>::new(ReuseFast);
+
+        manager.mark_interval_as_used(10, 19);
+        manager.mark_interval_as_used(15, 24);
+        manager.mark_value_as_used(20);
+
+        assert_eq!(manager.stats().allocated, 15);
+    }
+
+    #[test]
+    fn test_stats_counts_wrap_arounds() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 12);
+
+        for _ in 0..3 {
+            let id = manager.allocate();
+
+            manager.free(id);
+        }
+
+        assert_eq!(manager.stats().wrap_arounds, 1);
+    }
+
+    #[test]
+    fn test_stats_capacity_for_all_supported_types() {
+        assert_eq!(IdManager::<u8>::new(ReuseFast).stats().capacity, 256);
+        assert_eq!(IdManager::<u16>::new(ReuseFast).stats().capacity, 65536);
+        assert_eq!(IdManager::<u32>::new(ReuseFast).stats().capacity, 4294967296);
+        assert_eq!(IdManager::<u64>::new(ReuseFast).stats().capacity, 18446744073709551616);
+        assert_eq!(IdManager::<u128>::new(ReuseFast).stats().capacity, u128::MAX);
+    }
+
+    #[test]
+    fn test_allocate_every_u128() {
+        let mut manager = IdManager::<u128>::new(ReuseFast);
+
+        assert_eq!(manager.allocate(), 0);
+
+        manager.mark_interval_as_used(1, u128::MAX);
+
+        assert_eq!(manager.can_allocate(), false);
+        assert_eq!(manager.stats().allocated, u128::MAX);
+        assert_eq!(manager.stats().available(), 0);
+    }
+
+    #[test]
+    fn test_grow_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        for i in 10..21 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.can_allocate(), false);
+
+        assert_eq!(manager.grow_range(5, 25), Ok(()));
+
+        assert_eq!(manager.dump(), "[5,9], [21,25]");
+
+        assert_eq!(manager.stats().capacity, 21);
+
+        assert_eq!(manager.allocate(), 21);
+
+        manager.free(15);
+
+        assert_eq!(manager.dump(), "[5,9], [15], [22,25]");
+
+        assert_eq!(manager.grow_range(5, 24), Err(RangeError::InvalidRange));
+        assert_eq!(manager.grow_range(6, 25), Err(RangeError::InvalidRange));
+    }
+
+    #[test]
+    fn test_grow_range_joins_free_space() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseFast, 10, 20);
+
+        assert_eq!(manager.grow_range(0, 255), Ok(()));
+
+        assert_eq!(manager.dump(), "[0,255]");
+    }
+
+    #[test]
+    fn test_shrink_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        for i in 10..15 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.shrink_range(12, 50), Err(RangeError::IdsInUse));
+
+        assert_eq!(manager.shrink_range(10, 40), Ok(()));
+
+        assert_eq!(manager.dump(), "[15,40]");
+
+        assert_eq!(manager.stats().capacity, 31);
+
+        manager.free(10);
+        manager.free(11);
+
+        assert_eq!(manager.shrink_range(12, 40), Ok(()));
+
+        assert_eq!(manager.dump(), "[15,40]");
+
+        assert_eq!(manager.shrink_range(12, 41), Err(RangeError::InvalidRange));
+        assert_eq!(manager.shrink_range(20, 19), Err(RangeError::InvalidRange));
+    }
+
+    #[test]
+    fn test_shrink_range_moves_next_to_allocate_into_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        for i in 10..41 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        for i in 10..20 {
+            manager.free(i);
+        }
+
+        assert_eq!(manager.shrink_range(10, 40), Ok(()));
+
+        assert_eq!(manager.allocate(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "id out of range")]
+    fn test_free_after_shrink_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        assert_eq!(manager.shrink_range(10, 40), Ok(()));
+
+        manager.free(45);
+    }
+
+    fn two_ranges() -> Intervals<u16> {
+        let mut ranges = Intervals::<u16>::new();
+
+        ranges.insert_interval(100, 199);
+        ranges.insert_interval(1000, 1999);
+
+        ranges
+    }
+
+    #[test]
+    #[should_panic(expected = "ranges must not be empty")]
+    fn test_new_with_no_ranges() {
+        let _manager = IdManager::<u16>::new_with_ranges(ReuseFast, Intervals::new(), RangeOrder::Ascending);
+    }
+
+    #[test]
+    fn test_new_with_ranges() {
+        let manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        assert_eq!(manager.dump(), "[100,199], [1000,1999]");
+
+        assert_eq!(manager.stats().capacity, 1100);
+    }
+
+    #[test]
+    fn test_new_with_ranges_reuse_fast() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        assert_eq!(manager.allocate(), 100);
+
+        manager.mark_interval_as_used(101, 199);
+
+        assert_eq!(manager.allocate(), 1000);
+
+        manager.free(100);
+
+        assert_eq!(manager.allocate(), 100);
+    }
+
+    #[test]
+    fn test_new_with_ranges_reuse_fast_descending() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Descending);
+
+        assert_eq!(manager.allocate(), 1000);
+        assert_eq!(manager.allocate(), 1001);
+
+        manager.mark_interval_as_used(1002, 1999);
+
+        assert_eq!(manager.allocate(), 100);
+
+        manager.free(1000);
+
+        assert_eq!(manager.allocate(), 1000);
+    }
+
+    #[test]
+    fn test_new_with_ranges_reuse_slow() {
+        let mut ranges = Intervals::<u8>::new();
+
+        ranges.insert_interval(10, 11);
+        ranges.insert_interval(20, 21);
+
+        let mut manager = IdManager::<u8>::new_with_ranges(ReuseSlow, ranges, RangeOrder::Ascending);
+
+        assert_eq!(manager.allocate(), 10);
+        assert_eq!(manager.allocate(), 11);
+
+        manager.free(10);
+
+        assert_eq!(manager.allocate(), 20);
+        assert_eq!(manager.wrap_arounds(), 0);
+        assert_eq!(manager.allocate(), 21);
+        assert_eq!(manager.wrap_arounds(), 1);
+        assert_eq!(manager.allocate(), 10);
+
+        assert_eq!(manager.can_allocate(), false);
+    }
+
+    #[test]
+    fn test_new_with_ranges_reuse_slow_descending() {
+        let mut ranges = Intervals::<u8>::new();
+
+        ranges.insert_interval(0, 1);
+        ranges.insert_interval(20, 21);
+
+        let mut manager = IdManager::<u8>::new_with_ranges(ReuseSlow, ranges, RangeOrder::Descending);
+
+        assert_eq!(manager.allocate(), 20);
+        assert_eq!(manager.allocate(), 21);
+        assert_eq!(manager.allocate(), 0);
+
+        manager.free(20);
+
+        assert_eq!(manager.allocate(), 1);
+        assert_eq!(manager.allocate(), 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "id out of range")]
+    fn test_free_id_between_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        manager.free(500);
+    }
+
+    #[test]
+    #[should_panic(expected = "id out of range")]
+    fn test_mark_value_between_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        manager.mark_value_as_used(500);
+    }
+
+    #[test]
+    #[should_panic(expected = "lower id out of range")]
+    fn test_mark_interval_starting_between_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        manager.mark_interval_as_used(500, 1500);
+    }
+
+    #[test]
+    #[should_panic(expected = "interval out of range")]
+    fn test_mark_interval_spanning_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        manager.mark_interval_as_used(150, 1500);
+    }
+
+    #[test]
+    fn test_grow_and_shrink_range_with_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        assert_eq!(manager.grow_range(50, 2049), Ok(()));
+
+        assert_eq!(manager.dump(), "[50,199], [1000,2049]");
+
+        assert_eq!(manager.allocate(), 50);
+
+        assert_eq!(manager.shrink_range(1000, 2049), Err(RangeError::IdsInUse));
+
+        manager.free(50);
+
+        assert_eq!(manager.shrink_range(300, 500), Err(RangeError::InvalidRange));
+
+        assert_eq!(manager.shrink_range(1000, 2049), Ok(()));
+
+        assert_eq!(manager.dump(), "[1000,2049]");
+
+        assert_eq!(manager.stats().capacity, 1050);
+
+        assert_eq!(manager.allocate(), 1000);
+    }
+
+    #[test]
+    fn test_allocate_block() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+
+        manager.mark_value_as_used(14);
+
+        assert_eq!(manager.allocate_block(5), Some((15, 19)));
+
+        assert_eq!(manager.allocate_block(4), Some((10, 13)));
+
+        assert_eq!(manager.dump(), "[20,50]");
+
+        assert_eq!(manager.stats().allocated, 10);
+
+        assert_eq!(manager.allocate_block(32), None);
+
+        assert_eq!(manager.allocate_block(31), Some((20, 50)));
+
+        assert_eq!(manager.allocate_block(1), None);
+
+        manager.free_block(15, 19);
+
+        assert_eq!(manager.dump(), "[15,19]");
+
+        assert_eq!(manager.stats().allocated, 36);
+    }
+
+    #[test]
+    fn test_allocate_block_for_whole_range() {
+        let mut manager = IdManager::<u8>::new(ReuseFast);
+
+        assert_eq!(manager.allocate_block(256), Some((0, 255)));
+
+        assert_eq!(manager.can_allocate(), false);
+    }
+
+    #[test]
+    #[should_panic(expected = "block is not currently allocated")]
+    fn test_free_block_not_allocated() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+
+        assert_eq!(manager.allocate_block(5), Some((10, 14)));
+
+        manager.free_block(12, 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "block out of range")]
+    fn test_free_block_spanning_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseFast, two_ranges(), RangeOrder::Ascending);
+
+        manager.mark_interval_as_used(150, 199);
+        manager.mark_interval_as_used(1000, 1500);
+
+        manager.free_block(150, 1500);
+    }
+
+    #[test]
+    fn test_add_range() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 11);
+
+        assert_eq!(manager.allocate(), 10);
+        assert_eq!(manager.allocate(), 11);
+
+        assert_eq!(manager.add_range(12, 13), Ok(()));
+        assert_eq!(manager.add_range(30, 31), Ok(()));
+        assert_eq!(manager.add_range(11, 20), Err(RangeError::InvalidRange));
+
+        assert_eq!(manager.dump(), "[12,13], [30,31]");
+
+        assert_eq!(manager.stats().capacity, 6);
+
+        assert_eq!(manager.allocate(), 12);
+        assert_eq!(manager.allocate(), 13);
+        assert_eq!(manager.allocate(), 30);
+    }
+
+    #[test]
+    fn test_retire_interval() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseSlow, two_ranges(), RangeOrder::Ascending);
+
+        assert_eq!(manager.allocate(), 100);
+        assert_eq!(manager.reserve(), 101);
+
+        manager.retire_interval(100, 199);
+
+        assert_eq!(manager.dump(), "[1000,1999]");
+        assert_eq!(manager.stats().capacity, 1000);
+        assert_eq!(manager.stats().allocated, 0);
+        assert_eq!(manager.stats().reserved, 0);
+
+        // the last range stays but its ids no longer count
+
+        assert_eq!(manager.allocate(), 1000);
+
+        manager.retire_interval(1000, 1999);
+
+        assert_eq!(manager.dump(), "");
+        assert_eq!(manager.stats().capacity, 0);
+        assert_eq!(manager.stats().allocated, 0);
+        assert_eq!(manager.can_allocate(), false);
+    }
+
+    #[test]
+    fn test_remove_range() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseSlow, two_ranges(), RangeOrder::Ascending);
+
+        assert_eq!(manager.allocate(), 100);
+        assert_eq!(manager.allocate(), 101);
+        assert_eq!(manager.reserve(), 102);
+
+        assert_eq!(manager.remove_range(100, 199), Err(RangeError::IdsInUse));
+        assert_eq!(manager.remove_range(150, 1000), Err(RangeError::InvalidRange));
+
+        manager.commit(102);
+
+        // the allocated ids go along with the range
+
+        assert_eq!(manager.remove_range(100, 199), Ok(()));
+
+        assert_eq!(manager.dump(), "[1000,1999]");
+        assert_eq!(manager.stats().capacity, 1000);
+        assert_eq!(manager.stats().allocated, 0);
+
+        assert_eq!(manager.remove_range(1000, 1999), Err(RangeError::InvalidRange));
+
+        assert_eq!(manager.allocate(), 1000);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 210);
+
+        manager.mark_interval_as_used(201, 210);
+        manager.mark_value_as_used(11);
+
+        for i in [10, 12] {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        manager.free(10);
+
+        assert_eq!(manager.snapshot().to_string(), "policy=ReuseSlow; order=Ascending; ranges=[10,210]; next=13; free=[10], [13,200]");
+    }
+
+    #[test]
+    fn test_from_snapshot() {
+        let snapshot = "policy=ReuseSlow; order=Ascending; ranges=[10,210]; next=13; free=[10], [13,200]".parse().unwrap();
+
+        let mut manager = IdManager::<u8>::from_snapshot(snapshot).unwrap();
+
+        assert_eq!(manager.dump(), "[10], [13,200]");
+
+        assert_eq!(manager.stats().allocated, 12);
+
+        assert_eq!(manager.allocate(), 13);
+
+        manager.free(12);
+
+        assert_eq!(manager.dump(), "[10], [12], [14,200]");
+    }
+
+    #[test]
+    fn test_from_snapshot_with_ranges() {
+        let mut manager = IdManager::<u16>::new_with_ranges(ReuseSlow, two_ranges(), RangeOrder::Descending);
+
+        assert_eq!(manager.allocate(), 1000);
+
+        let mut restored = IdManager::<u16>::from_snapshot(manager.snapshot()).unwrap();
+
+        for _ in 0..1100 {
+            if !manager.can_allocate() {
+                break;
+            }
+
+            assert_eq!(restored.allocate(), manager.allocate());
+        }
+
+        assert_eq!(restored.can_allocate(), false);
+    }
 }
@@ -0,0 +1,16 @@
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IdManagerStats {
+    pub capacity: u128,
+    pub allocated: u128,
+    pub reserved: u128,
+    pub peak_allocated: u128,
+    pub free_intervals: usize,
+    pub largest_free_run: u128,
+    pub wrap_arounds: u64,
+}
+
+impl IdManagerStats {
+    pub fn available(&self) -> u128 {
+        self.capacity - self.allocated - self.reserved
+    }
+}
@@ -1,14 +1,22 @@
-pub trait IdType where Self: Ord + std::ops::Add<Self, Output=Self> + std::ops::Sub<Self, Output=Self> + Sized + num::One + std::fmt::Display + Copy
+use crate::step::Step;
+
+pub trait IdType where Self: Step + std::ops::Add<Self, Output=Self> + std::ops::Sub<Self, Output=Self> + Sized + num::One + std::str::FromStr
 {
-    const MAX: Self;
-    const MIN: Self;
+    fn to_u128(self) -> u128;
+
+    fn from_u128(value: u128) -> Self;
 }
 
 macro_rules! id_type_trait_impl {
     ($name:ident for $($t:ty)*) => ($(
     impl $name for $t {
-        const MAX : $t = <$t>::MAX;
-        const MIN : $t = <$t>::MIN;
+        fn to_u128(self) -> u128 {
+            self as u128
+        }
+
+        fn from_u128(value: u128) -> Self {
+            value as $t
+        }
     }
     )*)
 }
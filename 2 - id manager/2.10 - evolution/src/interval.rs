@@ -1,22 +1,33 @@
-use std::cmp::Ordering;
 use std::fmt;
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use std::str::FromStr;
 
-use num::One;
+use crate::step::{self, Step};
+use crate::interval_error::IntervalError;
 
-use crate::id_type::IdType;
+// ordered by lower bound and then by upper bound
 
-#[derive(PartialOrd, Eq, PartialEq, Clone)]
-pub struct Interval<T: IdType> {
+#[derive(Eq, PartialEq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+pub struct Interval<T: Step> {
     lower: T,
     upper: T,
 }
 
-impl<T: IdType> Interval<T> {
-    pub fn new(lower: T, upper: T) -> Self {
+impl<T: Step> Interval<T> {
+    pub fn new(lower: T, upper: T) -> Result<Self, IntervalError> {
         if upper < lower {
-            panic!("upper must be >= lower");
+            return Err(IntervalError::UpperBelowLower);
         }
 
+        Ok(Interval { lower, upper })
+    }
+
+    // for bounds that the caller has already checked
+
+    pub(crate) fn new_unchecked(lower: T, upper: T) -> Self {
+        debug_assert!(lower <= upper, "upper must be >= lower");
+
         Interval { lower, upper }
     }
 
@@ -35,6 +46,19 @@ impl<T: IdType> Interval<T> {
         self.upper
     }
 
+    // an interval always holds at least one value so there's no is_empty
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u128 {
+        // the full range of a u128 holds one more value than a u128 can count
+
+        T::steps_between(self.lower, self.upper).saturating_add(1)
+    }
+
+    pub fn is_single_value(&self) -> bool {
+        self.lower == self.upper
+    }
+
     fn dump(&self) -> String {
         format!("{}", self)
     }
@@ -52,7 +76,7 @@ impl<T: IdType> Interval<T> {
             return false;
         }
 
-        let next_value = value.upper + One::one();
+        let next_value = step::next(value.upper);
 
         next_value == self.lower
     }
@@ -62,77 +86,172 @@ impl<T: IdType> Interval<T> {
             return false;
         }
 
-        let next_value = value.lower - One::one();
+        let next_value = step::prev(value.lower);
 
         next_value == self.upper
     }
-}
 
-impl<T: IdType> fmt::Display for Interval<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.lower == self.upper {
-            write!(f, "[{}]", self.lower)
-        } else {
-            write!(f, "[{},{}]", self.lower, self.upper)
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        if !self.overlaps(other) {
+            return None;
         }
-    }
-}
 
-impl<T: IdType> Ord for Interval<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let lower_is = self.lower.cmp(&other.lower);
+        Some(Interval::new_unchecked(self.lower.max(other.lower), self.upper.min(other.upper)))
+    }
 
-        let upper_is = self.upper.cmp(&other.upper);
+    // splits into the values below value and the values from value upwards, either of which
+    // can be empty
 
-        if lower_is == Ordering::Less {
-            return Ordering::Less;
+    pub fn split_at(&self, value: T) -> (Option<Self>, Option<Self>) {
+        if value <= self.lower {
+            return (None, Some(*self));
         }
 
-        if upper_is == Ordering::Greater {
-            return Ordering::Greater;
+        if value > self.upper {
+            return (Some(*self), None);
         }
 
-        if upper_is == Ordering::Less {
-            return Ordering::Less;
-        }
+        (Some(Interval::new_unchecked(self.lower, step::prev(value))), Some(Interval::new_unchecked(value, self.upper)))
+    }
+
+    pub fn iter(&self) -> Values<T> {
+        Values { remaining: Some(*self) }
+    }
+}
+
+// the values of an interval in order
+
+pub struct Values<T: Step> {
+    remaining: Option<Interval<T>>,
+}
+
+impl<T: Step> Iterator for Values<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let remaining = self.remaining?;
 
-        if lower_is == Ordering::Greater {
-            return Ordering::Greater;
+        self.remaining = if remaining.is_single_value() { None } else { Some(Interval::new_unchecked(step::next(remaining.lower), remaining.upper)) };
+
+        Some(remaining.lower)
+    }
+}
+
+impl<T: Step> DoubleEndedIterator for Values<T> {
+    fn next_back(&mut self) -> Option<T> {
+        let remaining = self.remaining?;
+
+        self.remaining = if remaining.is_single_value() { None } else { Some(Interval::new_unchecked(remaining.lower, step::prev(remaining.upper))) };
+
+        Some(remaining.upper)
+    }
+}
+
+impl<T: Step> TryFrom<RangeInclusive<T>> for Interval<T> {
+    type Error = IntervalError;
+
+    fn try_from(range: RangeInclusive<T>) -> Result<Self, Self::Error> {
+        let (lower, upper) = range.into_inner();
+
+        Interval::new(lower, upper)
+    }
+}
+
+impl<T: Step> From<Interval<T>> for RangeInclusive<T> {
+    fn from(interval: Interval<T>) -> Self {
+        interval.lower..=interval.upper
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Step + serde::Serialize> serde::Serialize for Interval<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.lower, self.upper).serialize(serializer)
+    }
+}
+
+// accepts [lower, upper], "lower-upper" or "value"
+
+#[cfg(feature = "serde")]
+impl<'de, T: Step + FromStr + serde::Deserialize<'de>> serde::Deserialize<'de> for Interval<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Bounds<T> {
+            Pair(T, T),
+            Text(String),
         }
 
-        Ordering::Equal
+        let (lower, upper) = match Bounds::<T>::deserialize(deserializer)? {
+            Bounds::Pair(lower, upper) => (lower, upper),
+            Bounds::Text(text) => parse_bounds(&text).ok_or_else(|| D::Error::custom(format!("invalid interval: {}", text)))?,
+        };
+
+        Interval::new(lower, upper).map_err(D::Error::custom)
+    }
+}
+
+// values such as dates can contain '-' so each one is tried as the separator
+
+#[cfg(feature = "serde")]
+fn parse_bounds<T: Copy + FromStr>(text: &str) -> Option<(T, T)> {
+    if let Ok(value) = text.trim().parse::<T>() {
+        return Some((value, value));
+    }
+
+    text.match_indices('-').find_map(|(index, _)| {
+        let lower = text[..index].trim().parse::<T>().ok()?;
+
+        let upper = text[index + 1..].trim().parse::<T>().ok()?;
+
+        Some((lower, upper))
+    })
+}
+
+impl<T: Step> fmt::Display for Interval<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lower == self.upper {
+            write!(f, "[{}]", self.lower)
+        } else {
+            write!(f, "[{},{}]", self.lower, self.upper)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cmp::Ordering;
+
     use super::*;
 
     #[test]
-    #[should_panic(expected = "upper must be >= lower")]
     fn test_create_upper_less_than_lower() {
-        let _interval = Interval::<u8>::new(12, 11);
+        assert_eq!(Interval::<u8>::new(12, 11), Err(IntervalError::UpperBelowLower));
+
+        assert_eq!(IntervalError::UpperBelowLower.to_string(), "upper must be >= lower");
     }
 
     #[test]
     fn test_create_for_all_supported_types() {
         {
-            let _interval = Interval::<u8>::new(u8::MIN, u8::MAX);
+            let _interval = Interval::<u8>::new(u8::MIN, u8::MAX).unwrap();
         }
         {
-            let _interval = Interval::<u16>::new(u16::MIN, u16::MAX);
+            let _interval = Interval::<u16>::new(u16::MIN, u16::MAX).unwrap();
         }
         {
-            let _interval = Interval::<u32>::new(u32::MIN, u32::MAX);
+            let _interval = Interval::<u32>::new(u32::MIN, u32::MAX).unwrap();
         }
         {
-            let _interval = Interval::<u64>::new(u64::MIN, u64::MAX);
+            let _interval = Interval::<u64>::new(u64::MIN, u64::MAX).unwrap();
         }
         {
-            let _interval = Interval::<u128>::new(u128::MIN, u128::MAX);
+            let _interval = Interval::<u128>::new(u128::MIN, u128::MAX).unwrap();
         }
         {
-            let _interval = Interval::<usize>::new(usize::MIN, usize::MAX);
+            let _interval = Interval::<usize>::new(usize::MIN, usize::MAX).unwrap();
         }
     }
 
@@ -192,10 +311,39 @@ mod tests {
         assert_eq!(interval2.cmp(&interval1), Ordering::Equal);
         assert_eq!(interval1.cmp(&interval3), Ordering::Less);
         assert_eq!(interval1.cmp(&interval4), Ordering::Greater);
-        assert_eq!(interval1.cmp(&interval5), Ordering::Less);
+        assert_eq!(interval1.cmp(&interval5), Ordering::Greater);
         assert_eq!(interval1.cmp(&interval6), Ordering::Less);
     }
 
+    #[test]
+    fn test_total_order() {
+        let intervals: Vec<Interval<u8>> = [(1, 5), (2, 3), (2, 2), (1, 1), (5, 5), (0, 255)]
+            .iter()
+            .map(|(lower, upper)| Interval::new(*lower, *upper).unwrap())
+            .collect();
+
+        for a in intervals.iter() {
+            for b in intervals.iter() {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                assert_eq!(a.cmp(b) == Ordering::Equal, a == b);
+            }
+        }
+
+        let mut sorted = intervals.clone();
+
+        sorted.sort();
+
+        assert_eq!(sorted.iter().map(|interval| interval.to_string()).collect::<Vec<_>>(), ["[0,255]", "[1]", "[1,5]", "[2]", "[2,3]", "[5]"]);
+    }
+
+    #[test]
+    fn test_len() {
+        assert_eq!(Interval::<u8>::new(10, 10).unwrap().len(), 1);
+        assert_eq!(Interval::<u8>::new(10, 19).unwrap().len(), 10);
+        assert_eq!(Interval::<u8>::new(u8::MIN, u8::MAX).unwrap().len(), 256);
+        assert_eq!(Interval::<u128>::new(u128::MIN, u128::MAX).unwrap().len(), u128::MAX);
+    }
+
     #[test]
     fn test_dump() {
         {
@@ -207,7 +355,7 @@ mod tests {
             assert_eq!(interval.dump(), "[10,11]");
         }
         {
-            let interval = Interval::<u8>::new(22, 33);
+            let interval = Interval::<u8>::new(22, 33).unwrap();
 
             assert_eq!(interval.dump(), "[22,33]");
         }
@@ -364,4 +512,81 @@ mod tests {
 
         assert_eq!(interval1.extends_upper(&new_interval), false);
     }
+
+    #[test]
+    fn test_is_single_value() {
+        assert_eq!(Interval::<u8>::new_single_value_interval(10).is_single_value(), true);
+        assert_eq!(Interval::<u8>::new(10, 11).unwrap().is_single_value(), false);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let interval = Interval::<u8>::new(10, 20).unwrap();
+
+        assert_eq!(interval.intersection(&Interval::new(15, 30).unwrap()), Some(Interval::new(15, 20).unwrap()));
+        assert_eq!(interval.intersection(&Interval::new(0, 10).unwrap()), Some(Interval::new_single_value_interval(10)));
+        assert_eq!(interval.intersection(&Interval::new(12, 13).unwrap()), Some(Interval::new(12, 13).unwrap()));
+        assert_eq!(interval.intersection(&Interval::new(21, 30).unwrap()), None);
+    }
+
+    #[test]
+    fn test_split_at() {
+        let interval = Interval::<u8>::new(10, 20).unwrap();
+
+        assert_eq!(interval.split_at(15), (Some(Interval::new(10, 14).unwrap()), Some(Interval::new(15, 20).unwrap())));
+        assert_eq!(interval.split_at(20), (Some(Interval::new(10, 19).unwrap()), Some(Interval::new_single_value_interval(20))));
+        assert_eq!(interval.split_at(10), (None, Some(interval)));
+        assert_eq!(interval.split_at(0), (None, Some(interval)));
+        assert_eq!(interval.split_at(21), (Some(interval), None));
+        assert_eq!(interval.split_at(255), (Some(interval), None));
+    }
+
+    #[test]
+    fn test_iter() {
+        assert_eq!(Interval::<u8>::new(10, 13).unwrap().iter().collect::<Vec<_>>(), vec![10, 11, 12, 13]);
+        assert_eq!(Interval::<u8>::new(250, 255).unwrap().iter().rev().collect::<Vec<_>>(), vec![255, 254, 253, 252, 251, 250]);
+        assert_eq!(Interval::<u128>::new_single_value_interval(u128::MAX).iter().collect::<Vec<_>>(), vec![u128::MAX]);
+    }
+
+    #[test]
+    fn test_range_inclusive() {
+        let interval = Interval::<u16>::try_from(100..=200).unwrap();
+
+        assert_eq!(interval.dump(), "[100,200]");
+
+        assert_eq!(RangeInclusive::from(interval), 100..=200);
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 200..=100;
+
+        assert_eq!(Interval::<u16>::try_from(empty), Err(IntervalError::UpperBelowLower));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let interval = Interval::<u8>::new(10, 20).unwrap();
+
+        assert_eq!(serde_json::to_string(&interval).unwrap(), "[10,20]");
+
+        let parse = |json: &str| serde_json::from_str::<Interval<u8>>(json);
+
+        assert_eq!(parse("[10,20]").unwrap(), interval);
+        assert_eq!(parse("\"10-20\"").unwrap(), interval);
+        assert_eq!(parse("\"10\"").unwrap(), Interval::new_single_value_interval(10));
+
+        assert_eq!(parse("[20,10]").unwrap_err().to_string(), "upper must be >= lower");
+        assert_eq!(parse("\"10-300\"").unwrap_err().to_string(), "invalid interval: 10-300");
+        assert_eq!(parse("[10]").is_err(), true);
+    }
+
+    #[cfg(all(feature = "serde", feature = "chrono"))]
+    #[test]
+    fn test_serde_dates() {
+        use chrono::NaiveDate;
+
+        let interval = serde_json::from_str::<Interval<NaiveDate>>("\"2024-02-27-2024-03-02\"").unwrap();
+
+        assert_eq!(interval.len(), 5);
+    }
 }
@@ -0,0 +1,17 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IntervalError {
+    UpperBelowLower,
+}
+
+impl fmt::Display for IntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntervalError::UpperBelowLower => write!(f, "upper must be >= lower"),
+        }
+    }
+}
+
+impl Error for IntervalError {}
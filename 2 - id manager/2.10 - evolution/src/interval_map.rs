@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::step::{self, Step};
+use crate::interval::Interval;
+
+// Maps disjoint intervals to values, e.g. id ranges to the shard that owns them. Inserting an
+// interval replaces the values of any intervals that it overlaps, splitting them where they
+// extend beyond it, and intervals that are adjacent and hold equal values are merged.
+
+#[derive(Clone)]
+pub struct IntervalMap<T: Step, V: Clone + PartialEq> {
+    intervals: BTreeMap<T, (T, V)>,     // upper -> (lower, value), as in Intervals
+}
+
+impl<T: Step, V: Clone + PartialEq> IntervalMap<T, V> {
+    pub fn new() -> Self {
+        IntervalMap {
+            intervals: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn interval_count(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (Interval<T>, &V)> + '_ {
+        self.intervals.iter().map(|(upper, (lower, value))| (Interval::new_unchecked(*lower, *upper), value))
+    }
+
+    pub fn get(&self, point: T) -> Option<&V> {
+        self.get_interval(point).map(|(_, value)| value)
+    }
+
+    pub fn get_interval(&self, point: T) -> Option<(Interval<T>, &V)> {
+        let (upper, (lower, value)) = self.intervals.range(point..).next()?;
+
+        if *lower > point {
+            return None;
+        }
+
+        Some((Interval::new_unchecked(*lower, *upper), value))
+    }
+
+    pub fn insert(&mut self, lower: T, upper: T, value: V) {
+        if upper < lower {
+            panic!("upper must be >= lower");
+        }
+
+        self.remove(lower, upper);
+
+        let mut lower = lower;
+
+        if lower > T::MIN {
+            let prev = step::prev(lower);
+
+            if self.intervals.get(&prev).is_some_and(|(_, prev_value)| *prev_value == value) {
+                lower = self.intervals.remove(&prev).unwrap().0;
+            }
+        }
+
+        if upper < T::MAX {
+            let next = self.intervals.range(step::next(upper)..).next();
+
+            if let Some((next_upper, (next_lower, next_value))) = next {
+                if *next_lower == step::next(upper) && *next_value == value {
+                    // extends the next interval downwards in place
+
+                    let next_upper = *next_upper;
+
+                    self.intervals.get_mut(&next_upper).unwrap().0 = lower;
+
+                    return;
+                }
+            }
+        }
+
+        self.intervals.insert(upper, (lower, value));
+    }
+
+    // returns the number of values that no longer have a value
+
+    pub fn remove(&mut self, lower: T, upper: T) -> u128 {
+        let mut removed: u128 = 0;
+
+        let overlapping: Vec<(T, T)> = self
+            .intervals
+            .range(lower..)
+            .take_while(|(_, (interval_lower, _))| *interval_lower <= upper)
+            .map(|(interval_upper, (interval_lower, _))| (*interval_lower, *interval_upper))
+            .collect();
+
+        for (interval_lower, interval_upper) in overlapping {
+            let overlap = Interval::new_unchecked(interval_lower.max(lower), interval_upper.min(upper));
+
+            removed = removed.saturating_add(overlap.len());
+
+            let value = if interval_upper > upper {
+                let entry = self.intervals.get_mut(&interval_upper).unwrap();
+
+                entry.0 = step::next(upper);
+
+                entry.1.clone()
+            } else {
+                self.intervals.remove(&interval_upper).unwrap().1
+            };
+
+            if interval_lower < lower {
+                self.intervals.insert(step::prev(lower), (interval_lower, value));
+            }
+        }
+
+        removed
+    }
+}
+
+impl<T: Step, V: Clone + PartialEq> Default for IntervalMap<T, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Step, V: Clone + PartialEq + fmt::Display> fmt::Display for IntervalMap<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+
+        for (interval, value) in self.iter() {
+            if !first {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{} -> {}", interval, value)?;
+
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let mut map = IntervalMap::<u16, &str>::new();
+
+        assert_eq!(map.get(100), None);
+
+        map.insert(100, 199, "A");
+        map.insert(300, 300, "B");
+
+        assert_eq!(map.get(99), None);
+        assert_eq!(map.get(100), Some(&"A"));
+        assert_eq!(map.get(199), Some(&"A"));
+        assert_eq!(map.get(200), None);
+        assert_eq!(map.get(300), Some(&"B"));
+        assert_eq!(map.get(65535), None);
+
+        assert_eq!(map.get_interval(150), Some((Interval::new(100, 199).unwrap(), &"A")));
+    }
+
+    #[test]
+    fn test_insert_splits_overlapping_intervals() {
+        let mut map = IntervalMap::<u16, &str>::new();
+
+        map.insert(100, 199, "A");
+        map.insert(150, 159, "B");
+
+        assert_eq!(map.to_string(), "[100,149] -> A, [150,159] -> B, [160,199] -> A");
+
+        map.insert(140, 170, "C");
+
+        assert_eq!(map.to_string(), "[100,139] -> A, [140,170] -> C, [171,199] -> A");
+
+        map.insert(0, 1000, "D");
+
+        assert_eq!(map.to_string(), "[0,1000] -> D");
+    }
+
+    #[test]
+    fn test_insert_merges_adjacent_equal_values() {
+        let mut map = IntervalMap::<u8, &str>::new();
+
+        map.insert(10, 19, "A");
+        map.insert(30, 39, "A");
+        map.insert(20, 29, "A");
+
+        assert_eq!(map.to_string(), "[10,39] -> A");
+        assert_eq!(map.interval_count(), 1);
+
+        map.insert(40, 49, "B");
+        map.insert(0, 9, "B");
+
+        assert_eq!(map.to_string(), "[0,9] -> B, [10,39] -> A, [40,49] -> B");
+
+        map.insert(15, 24, "A");
+
+        assert_eq!(map.to_string(), "[0,9] -> B, [10,39] -> A, [40,49] -> B");
+
+        map.insert(10, 39, "B");
+
+        assert_eq!(map.to_string(), "[0,49] -> B");
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut map = IntervalMap::<u8, &str>::new();
+
+        map.insert(10, 19, "A");
+        map.insert(20, 29, "B");
+
+        assert_eq!(map.remove(15, 24), 10);
+        assert_eq!(map.remove(0, 5), 0);
+
+        assert_eq!(map.to_string(), "[10,14] -> A, [25,29] -> B");
+
+        assert_eq!(map.remove(u8::MIN, u8::MAX), 10);
+
+        assert_eq!(map.is_empty(), true);
+    }
+
+    #[test]
+    fn test_ends_of_range() {
+        let mut map = IntervalMap::<u8, u32>::new();
+
+        map.insert(u8::MIN, 127, 1);
+        map.insert(128, u8::MAX, 1);
+
+        assert_eq!(map.to_string(), "[0,255] -> 1");
+
+        map.insert(u8::MAX, u8::MAX, 2);
+        map.insert(u8::MIN, u8::MIN, 3);
+
+        assert_eq!(map.to_string(), "[0] -> 3, [1,254] -> 1, [255] -> 2");
+    }
+
+    #[test]
+    #[should_panic(expected = "upper must be >= lower")]
+    fn test_insert_upper_less_than_lower() {
+        let mut map = IntervalMap::<u8, u32>::new();
+
+        map.insert(12, 11, 1);
+    }
+}
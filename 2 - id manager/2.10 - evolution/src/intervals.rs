@@ -1,20 +1,25 @@
-use std::collections::Bound::{Excluded, Included, Unbounded};
-use std::collections::BTreeSet;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::str::FromStr;
 
-use num::One;
-
-use crate::id_type::IdType;
+use crate::step::{self, Step};
 use crate::interval::Interval;
+use crate::parse_error::ParseError;
+use crate::validation_report::{IntervalsViolation, ValidationReport};
+
+// The intervals are keyed by their upper bound. Allocating takes the lowest value of an
+// interval and freeing usually extends the lower bound of the interval above it, so both only
+// need to update the lower bound in place rather than removing and reinserting the interval.
 
-pub struct Intervals<T: IdType> {
-    intervals: BTreeSet<Interval<T>>,
+#[derive(Clone)]
+pub struct Intervals<T: Step> {
+    intervals: BTreeMap<T, T>,      // upper -> lower
 }
 
-impl<T: IdType> Intervals<T> {
+impl<T: Step> Intervals<T> {
     pub fn new() -> Self {
         Intervals::<T> {
-            intervals: BTreeSet::new(),
+            intervals: BTreeMap::new(),
         }
     }
 
@@ -22,16 +27,32 @@ impl<T: IdType> Intervals<T> {
         self.intervals.is_empty()
     }
 
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Interval<T>> + '_ {
+        self.intervals.iter().map(|(upper, lower)| Interval::new_unchecked(*lower, *upper))
+    }
+
+    pub fn interval_count(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn value_count(&self) -> u128 {
+        self.iter().fold(0, |count: u128, interval| count.saturating_add(interval.len()))
+    }
+
+    pub fn largest_interval_len(&self) -> u128 {
+        self.iter().map(|interval| interval.len()).max().unwrap_or(0)
+    }
+
     pub fn insert_interval(&mut self, lower: T, upper: T) -> bool {
-        let interval = Interval::new(lower, upper);
+        if upper < lower {
+            panic!("upper must be >= lower");
+        }
 
-        self.insert(interval)
+        self.insert(lower, upper)
     }
 
     pub fn insert_value(&mut self, value: T) -> bool {
-        let interval = Interval::new_single_value_interval(value);
-
-        self.insert(interval)
+        self.insert(value, value)
     }
 
     pub fn dump(&self) -> String {
@@ -39,232 +60,314 @@ impl<T: IdType> Intervals<T> {
     }
 
     pub fn remove_first_interval(&mut self) -> Interval<T> {
-        let first_it = self.intervals.iter().next();
+        if let Some((upper, lower)) = self.intervals.pop_first() {
+            self.check_invariants();
 
-        if let Some(first_interval) = first_it {
-            let ret = first_interval.clone();
-
-            self.intervals.remove(&first_interval.clone());
-
-            return ret;
+            return Interval::new_unchecked(lower, upper);
         }
 
         panic!("Empty!");
     }
 
     pub fn remove_first_value(&mut self) -> T {
-        let first_interval = self.remove_first_interval();
+        let Some(mut first) = self.intervals.first_entry() else {
+            panic!("Empty!");
+        };
 
-        let first_value = first_interval.lower();
+        let first_value = *first.get();
 
-        if first_interval.lower() != first_interval.upper()
-        {
-            self.intervals
-                .insert(Interval::new(first_value + One::one(), first_interval.upper()));
+        if first_value == *first.key() {
+            first.remove();
+        } else {
+            *first.get_mut() = step::next(first_value);
         }
 
+        self.check_invariants();
+
         first_value
     }
 
+    pub fn remove_first_value_in(&mut self, lower: T, upper: T) -> Option<T> {
+        let (_, first) = self.intervals.range(lower..).next()?;
+
+        let value = (*first).max(lower);
+
+        if value > upper {
+            return None;
+        }
+
+        self.remove_value(value);
+
+        Some(value)
+    }
+
     pub fn remove_value(&mut self, value: T) -> bool {
-        if let Some(interval) = self.find(&Interval::new_single_value_interval(value)) {
-            if interval.lower() < value {
-                self.intervals
-                    .insert(Interval::new(interval.lower(), value - One::one()));
-            }
+        let Some((&upper, lower)) = self.intervals.range_mut(value..).next() else {
+            return false;
+        };
 
-            if value < interval.upper() {
-                self.intervals
-                    .insert(Interval::new(value + One::one(), interval.upper()));
-            }
+        let interval_lower = *lower;
+
+        if interval_lower > value {
+            return false;
+        }
 
-            self.intervals.remove(&interval);
+        // shrinks the interval from below in place, any values below the removed one become a
+        // new interval
 
-            return true;
+        if value < upper {
+            *lower = step::next(value);
+        } else {
+            self.intervals.remove(&upper);
         }
 
-        false
-    }
+        if interval_lower < value {
+            self.intervals.insert(step::prev(value), interval_lower);
+        }
 
-    pub fn remove_interval(&mut self, lower: T, upper: T) {
-        let mut remove_these: BTreeSet<Interval<T>> = BTreeSet::new();
+        self.check_invariants();
 
-        let mut add_these: BTreeSet<Interval<T>> = BTreeSet::new();
+        true
+    }
 
-        {
-            let interval_to_remove = Interval::new(lower, upper);
+    pub fn remove_interval(&mut self, lower: T, upper: T) -> u128 {
+        let mut removed: u128 = 0;
 
-            let intervals = self.intervals.range((Included(Interval::new(T::MIN, lower)), Unbounded));
+        let overlapping: Vec<(T, T)> = self
+            .intervals
+            .range(lower..)
+            .take_while(|(_, interval_lower)| **interval_lower <= upper)
+            .map(|(interval_upper, interval_lower)| (*interval_lower, *interval_upper))
+            .collect();
 
-            for interval in intervals {
-                if interval_to_remove.overlaps(interval) {
-                    remove_these.insert(interval.clone());
-                }
-                else if interval.contains_value(lower) || interval.contains_value(upper) {
-                    remove_these.insert(interval.clone());
-                }
+        for (interval_lower, interval_upper) in overlapping {
+            let overlap = Interval::new_unchecked(interval_lower.max(lower), interval_upper.min(upper));
 
-                if interval.lower() < lower && interval.upper() >= lower {
-                    add_these.insert(Interval::<T>::new(interval.lower(), lower - T::one()));
-                }
+            removed = removed.saturating_add(overlap.len());
 
-                if interval.upper() > upper && interval.lower() <= upper {
-                    add_these.insert(Interval::<T>::new(upper + T::one(), interval.upper()));
-                }
+            if interval_upper > upper {
+                self.intervals.insert(interval_upper, step::next(upper));
+            } else {
+                self.intervals.remove(&interval_upper);
+            }
+
+            if interval_lower < lower {
+                self.intervals.insert(step::prev(lower), interval_lower);
             }
         }
 
-        for interval in remove_these {
-            self.intervals.remove(&interval);
+        self.check_invariants();
+
+        removed
+    }
+
+    pub fn contains_value(&self, value: T) -> bool {
+        self.contains_interval(value, value)
+    }
+
+    pub fn union(&mut self, other: &Intervals<T>) {
+        for (upper, lower) in other.intervals.iter() {
+            self.remove_interval(*lower, *upper);
+
+            self.insert(*lower, *upper);
         }
+    }
 
-        for interval in add_these {
-            self.intervals.insert(interval);
+    pub fn contains_interval(&self, lower: T, upper: T) -> bool {
+        match self.intervals.range(lower..).next() {
+            Some((interval_upper, interval_lower)) => *interval_lower <= lower && *interval_upper >= upper,
+            None => false,
         }
     }
 
-    fn find(&self, interval: &Interval<T>) -> Option<Interval<T>> {
-        let before = self.intervals.range((Unbounded, Included(interval)));
+    pub fn validate(&self) -> Result<(), ValidationReport<T>> {
+        let mut violations = Vec::new();
 
-        let prev = before.max();
+        let mut previous: Option<(T, T)> = None;
 
-        if let Some(prev) = prev {
-            if prev.overlaps(interval) {
-                return Some(prev.clone());
+        for (upper, lower) in self.intervals.iter() {
+            let interval = (*lower, *upper);
+
+            if lower > upper {
+                violations.push(IntervalsViolation::Inverted(interval));
+            } else if let Some(previous) = previous {
+                if interval.0 < previous.0 {
+                    violations.push(IntervalsViolation::Unsorted(previous, interval));
+                } else if interval.0 <= previous.1 {
+                    violations.push(IntervalsViolation::Overlapping(previous, interval));
+                } else if previous.1.successor() == Some(interval.0) {
+                    violations.push(IntervalsViolation::Unmerged(previous, interval));
+                }
             }
+
+            previous = Some(interval);
         }
 
-        let after = self.intervals.range((Included(interval), Unbounded));
+        if !violations.is_empty() {
+            return Err(ValidationReport { dump: self.dump(), violations });
+        }
 
-        let next = after.min();
+        Ok(())
+    }
 
-        if let Some(next) = next {
-            if next.overlaps(interval) {
-                return Some(next.clone());
-            }
+    #[cfg(feature = "validate")]
+    fn check_invariants(&self) {
+        if let Err(report) = self.validate() {
+            panic!("{}", report);
         }
-
-        None
     }
 
-    fn insert(&mut self, interval: Interval<T>) -> bool {
-        let intervals_after = self.intervals.range((Included(&interval), Unbounded));
+    #[cfg(not(feature = "validate"))]
+    fn check_invariants(&self) {}
 
-        let next_it = intervals_after.min();
+    // adds an interval that must come after all of the intervals in the set
 
-        let next_is = next_it.is_some();
+    fn append(&mut self, lower: T, upper: T) -> Result<(), ParseError> {
+        if let Some((last_upper, _)) = self.intervals.last_key_value() {
+            if lower <= *last_upper {
+                return Err(if upper < *last_upper { ParseError::Unsorted } else { ParseError::Overlapping });
+            }
+        }
 
-        if next_is {
-            let next_ref = next_it.unwrap();
+        self.insert(lower, upper);
 
-            if next_ref.overlaps(&interval)
-            {
+        Ok(())
+    }
+
+    fn insert(&mut self, lower: T, upper: T) -> bool {
+        // the first interval that ends at or after lower is the only one that could overlap
+
+        let next = self.intervals.range(lower..).next().map(|(next_upper, next_lower)| (*next_lower, *next_upper));
+
+        if let Some((next_lower, _)) = next {
+            if next_lower <= upper {
                 return false;
             }
         }
 
-        let intervals_before = self.intervals.range((Unbounded, Excluded(&interval)));
+        let next_extends = match next {
+            Some((next_lower, _)) => upper < T::MAX && next_lower == step::next(upper),
+            None => false,
+        };
 
-        let prev_it = intervals_before.max();
+        // takes the interval ending just below lower out of the map, it's either joined to the
+        // next interval or reinserted under its new upper bound
 
-        let prev_is = prev_it.is_some();
+        let prev = if lower > T::MIN { self.intervals.remove(&step::prev(lower)) } else { None };
 
-        if prev_is {
-            let prev_ref = prev_it.unwrap();
+        match (prev, next_extends) {
+            (Some(prev_lower), true) => {
+                // joins the previous and next intervals
 
-            if prev_ref.overlaps(&interval)
-            {
-                return false;
+                *self.intervals.get_mut(&next.unwrap().1).unwrap() = prev_lower;
             }
-        }
+            (None, true) => {
+                // extends the next interval
 
-        if next_is && prev_is {
-            self.insert_or_join_intervals(interval, next_it.unwrap().clone(), prev_it.unwrap().clone());
-        } else if next_is {
-            self.insert_or_merge_with_next(interval, next_it.unwrap().clone());
-        } else if prev_is {
-            self.insert_or_merge_with_prev(interval, prev_it.unwrap().clone());
-        } else {
-            self.intervals.insert(interval);
+                *self.intervals.get_mut(&next.unwrap().1).unwrap() = lower;
+            }
+            (Some(prev_lower), false) => {
+                // extends the previous interval
+
+                self.intervals.insert(upper, prev_lower);
+            }
+            (None, false) => {
+                self.intervals.insert(upper, lower);
+            }
         }
 
+        self.check_invariants();
+
         true
     }
+}
 
-    fn insert_or_join_intervals(&mut self, interval: Interval<T>, next: Interval<T>, prev: Interval<T>) {
-        let next_extends = next.extends_lower(&interval);
+// parses the output of dump(), the intervals must be sorted and must not overlap
 
-        let prev_extends = prev.extends_upper(&interval);
+impl<T: Step + FromStr> FromStr for Intervals<T> {
+    type Err = ParseError;
 
-        if next_extends && prev_extends
-        {
-            // merges the prev and next intervals
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut intervals = Intervals::<T>::new();
 
-            let new_interval = Interval::new(prev.lower(), next.upper());
+        for part in text.split_inclusive(']') {
+            let part = part.trim_start_matches(',').trim();
 
-            self.intervals.remove(&prev);
-            self.intervals.remove(&next);
-            self.intervals.insert(new_interval);
-        } else if next_extends
-        {
-            // extends the next interval
+            if part.is_empty() {
+                continue;
+            }
 
-            let new_interval = Interval::new(interval.lower(), next.upper());
+            let values = part
+                .strip_prefix('[')
+                .and_then(|values| values.strip_suffix(']'))
+                .ok_or_else(|| ParseError::InvalidFormat(part.to_string()))?;
 
-            self.intervals.remove(&next);
-            self.intervals.insert(new_interval);
-        } else if prev_extends
-        {
-            // extends the previous interval
+            let values: Vec<T> = values
+                .split(',')
+                .map(|value| value.trim().parse::<T>().map_err(|_| ParseError::InvalidValue(value.trim().to_string())))
+                .collect::<Result<Vec<T>, ParseError>>()?;
 
-            let new_interval = Interval::new(prev.lower(), interval.upper());
+            let (lower, upper) = match values.as_slice() {
+                [value] => (*value, *value),
+                [lower, upper] => (*lower, *upper),
+                _ => return Err(ParseError::InvalidFormat(part.to_string())),
+            };
 
-            self.intervals.remove(&prev);
-            self.intervals.insert(new_interval);
-        } else {
-            self.intervals.insert(interval);
+            if upper < lower {
+                return Err(ParseError::UpperBelowLower(part.to_string()));
+            }
+
+            intervals.append(lower, upper)?;
         }
+
+        Ok(intervals)
     }
+}
 
-    fn insert_or_merge_with_next(&mut self, interval: Interval<T>, next: Interval<T>) {
-        if next.extends_lower(&interval) {
+#[cfg(feature = "serde")]
+impl<T: Step + serde::Serialize> serde::Serialize for Intervals<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
 
-            // extends the next interval
+// a list of intervals that, as with from_str(), must be sorted and must not overlap
 
-            let new_interval = Interval::new(interval.lower(), next.upper());
+#[cfg(feature = "serde")]
+impl<'de, T: Step + FromStr + serde::Deserialize<'de>> serde::Deserialize<'de> for Intervals<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut intervals = Intervals::<T>::new();
 
-            self.intervals.remove(&next);
-            self.intervals.insert(new_interval);
-        } else {
-            self.intervals.insert(interval);
+        for interval in Vec::<Interval<T>>::deserialize(deserializer)? {
+            intervals.append(interval.lower(), interval.upper()).map_err(serde::de::Error::custom)?;
         }
-    }
-
-    fn insert_or_merge_with_prev(&mut self, interval: Interval<T>, prev: Interval<T>) {
-        if prev.extends_upper(&interval) {
 
-            // extends the previous interval
-
-            let new_interval = Interval::new(prev.lower(), interval.upper());
+        Ok(intervals)
+    }
+}
 
-            self.intervals.remove(&prev);
-            self.intervals.insert(new_interval);
-        } else {
-            self.intervals.insert(interval);
-        }
+impl<T: Step> Default for Intervals<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<T: IdType> fmt::Display for Intervals<T> {
+impl<T: Step> fmt::Display for Intervals<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut first = true;
 
-        for interval in self.intervals.iter() {
+        // writes the bounds directly so that a set that fails validation can still be dumped
+
+        for (upper, lower) in self.intervals.iter() {
             if !first {
                 write!(f, ", ")?;
             }
 
-            write!(f, "{}", interval)?;
+            if lower == upper {
+                write!(f, "[{}]", lower)?;
+            } else {
+                write!(f, "[{},{}]", lower, upper)?;
+            }
 
             if first {
                 first = false;
@@ -811,52 +914,236 @@ mod tests {
 
         assert_eq!(intervals.dump(), "[0,255]");
 
-        intervals.remove_interval(10,30);               // middle
+        assert_eq!(intervals.remove_interval(10,30), 21);               // middle
 
         assert_eq!(intervals.dump(), "[0,9], [31,255]");
 
-        intervals.remove_interval(10,30);               // duplicate
+        assert_eq!(intervals.remove_interval(10,30), 0);               // duplicate
 
         assert_eq!(intervals.dump(), "[0,9], [31,255]");
 
-        intervals.remove_interval(50,60);
+        assert_eq!(intervals.remove_interval(50,60), 11);
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [61,255]");
 
-        intervals.remove_interval(70,90);
+        assert_eq!(intervals.remove_interval(70,90), 21);
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [61,69], [91,255]");
 
-        intervals.remove_interval(68,91);           // first/last
+        assert_eq!(intervals.remove_interval(68,91), 3);           // first/last
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [61,67], [92,255]");
 
-        intervals.remove_interval(65,93);
+        assert_eq!(intervals.remove_interval(65,93), 5);
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [61,64], [94,255]");
 
-        intervals.remove_interval(50,93);           // spans
+        assert_eq!(intervals.remove_interval(50,93), 4);           // spans
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [94,255]");
 
-        intervals.remove_interval(50,93);
+        assert_eq!(intervals.remove_interval(50,93), 0);
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [94,255]");
 
-        intervals.remove_interval(200,255);         // end
+        assert_eq!(intervals.remove_interval(200,255), 56);         // end
 
         assert_eq!(intervals.dump(), "[0,9], [31,49], [94,199]");
 
-        intervals.remove_interval(0,5);             // start
+        assert_eq!(intervals.remove_interval(0,5), 6);             // start
 
         assert_eq!(intervals.dump(), "[6,9], [31,49], [94,199]");
 
-        intervals.remove_interval(7,198);           // most
+        assert_eq!(intervals.remove_interval(7,198), 127);           // most
 
         assert_eq!(intervals.dump(), "[6], [199]");
 
-        intervals.remove_interval(0,255);           // all
+        assert_eq!(intervals.remove_interval(0,255), 2);           // all
 
         assert_eq!(intervals.dump(), "");
     }
+
+    #[test]
+    fn test_contains_interval()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(intervals.contains_interval(0, 0), false);
+
+        assert_eq!(intervals.insert_interval(10, 20), true);
+        assert_eq!(intervals.insert_interval(30, 40), true);
+
+        assert_eq!(intervals.contains_interval(10, 20), true);
+        assert_eq!(intervals.contains_interval(12, 18), true);
+        assert_eq!(intervals.contains_interval(30, 30), true);
+        assert_eq!(intervals.contains_interval(9, 20), false);
+        assert_eq!(intervals.contains_interval(10, 21), false);
+        assert_eq!(intervals.contains_interval(15, 35), false);
+        assert_eq!(intervals.contains_interval(21, 29), false);
+    }
+
+    #[test]
+    fn test_remove_first_value_in()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(intervals.remove_first_value_in(0, 255), None);
+
+        assert_eq!(intervals.insert_interval(10, 20), true);
+        assert_eq!(intervals.insert_interval(30, 40), true);
+
+        assert_eq!(intervals.remove_first_value_in(0, 255), Some(10));
+        assert_eq!(intervals.remove_first_value_in(25, 35), Some(30));
+        assert_eq!(intervals.remove_first_value_in(15, 35), Some(15));
+        assert_eq!(intervals.remove_first_value_in(21, 29), None);
+        assert_eq!(intervals.remove_first_value_in(41, 255), None);
+
+        assert_eq!(intervals.dump(), "[11,14], [16,20], [31,40]");
+    }
+
+    #[test]
+    fn test_value_count()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(intervals.value_count(), 0);
+
+        assert_eq!(intervals.insert_interval(10, 20), true);
+        assert_eq!(intervals.insert_value(30), true);
+
+        assert_eq!(intervals.value_count(), 12);
+    }
+
+    #[test]
+    fn test_from_str()
+    {
+        assert_eq!("".parse::<Intervals<u8>>().unwrap().dump(), "");
+        assert_eq!("[10]".parse::<Intervals<u8>>().unwrap().dump(), "[10]");
+        assert_eq!("[10], [12,200]".parse::<Intervals<u8>>().unwrap().dump(), "[10], [12,200]");
+        assert_eq!(" [0,9],[11, 255] ".parse::<Intervals<u8>>().unwrap().dump(), "[0,9], [11,255]");
+        assert_eq!("[10], [11,200]".parse::<Intervals<u8>>().unwrap().dump(), "[10,200]");
+    }
+
+    #[test]
+    fn test_from_str_invalid()
+    {
+        assert_eq!("10".parse::<Intervals<u8>>().err(), Some(ParseError::InvalidFormat("10".to_string())));
+        assert_eq!("[1,2,3]".parse::<Intervals<u8>>().err(), Some(ParseError::InvalidFormat("[1,2,3]".to_string())));
+        assert_eq!("[1,2".parse::<Intervals<u8>>().err(), Some(ParseError::InvalidFormat("[1,2".to_string())));
+        assert_eq!("[5,4]".parse::<Intervals<u8>>().err(), Some(ParseError::UpperBelowLower("[5,4]".to_string())));
+        assert_eq!("[256]".parse::<Intervals<u8>>().err(), Some(ParseError::InvalidValue("256".to_string())));
+        assert_eq!("[x]".parse::<Intervals<u8>>().err(), Some(ParseError::InvalidValue("x".to_string())));
+        assert_eq!("[10,20], [5]".parse::<Intervals<u8>>().err(), Some(ParseError::Unsorted));
+        assert_eq!("[10,20], [15,25]".parse::<Intervals<u8>>().err(), Some(ParseError::Overlapping));
+    }
+
+    #[test]
+    fn test_contains_value()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(intervals.insert_interval(10, 20), true);
+
+        assert_eq!(intervals.contains_value(9), false);
+        assert_eq!(intervals.contains_value(10), true);
+        assert_eq!(intervals.contains_value(20), true);
+        assert_eq!(intervals.contains_value(21), false);
+    }
+
+    #[test]
+    fn test_union()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(intervals.insert_interval(10, 20), true);
+        assert_eq!(intervals.insert_interval(30, 40), true);
+        assert_eq!(intervals.insert_value(255), true);
+
+        let mut other = Intervals::<u8>::new();
+
+        assert_eq!(other.insert_interval(0, 5), true);
+        assert_eq!(other.insert_interval(15, 29), true);
+        assert_eq!(other.insert_interval(35, 50), true);
+        assert_eq!(other.insert_value(254), true);
+
+        intervals.union(&other);
+
+        assert_eq!(intervals.dump(), "[0,5], [10,50], [254,255]");
+
+        intervals.union(&Intervals::new());
+
+        assert_eq!(intervals.dump(), "[0,5], [10,50], [254,255]");
+    }
+
+    #[test]
+    fn test_validate()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        assert_eq!(intervals.validate(), Ok(()));
+
+        assert_eq!(intervals.insert_interval(10, 20), true);
+        assert_eq!(intervals.insert_interval(22, 30), true);
+        assert_eq!(intervals.insert_value(255), true);
+
+        assert_eq!(intervals.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_violations()
+    {
+        let mut intervals = Intervals::<u8>::new();
+
+        intervals.intervals.insert(20, 10);
+        intervals.intervals.insert(30, 21);
+        intervals.intervals.insert(40, 30);
+        intervals.intervals.insert(50, 50);
+
+        let report = intervals.validate().unwrap_err();
+
+        assert_eq!(report.violations, vec![
+            IntervalsViolation::Unmerged((10, 20), (21, 30)),
+            IntervalsViolation::Overlapping((21, 30), (30, 40)),
+        ]);
+
+        assert_eq!(report.to_string(), "invalid intervals [10,20], [21,30], [30,40], [50]\n  [10,20] and [21,30] are adjacent but not merged\n  [21,30] overlaps [30,40]");
+
+        let mut intervals = Intervals::<u8>::new();
+
+        intervals.intervals.insert(40, 10);
+        intervals.intervals.insert(30, 20);
+
+        assert_eq!(intervals.validate().unwrap_err().violations, vec![IntervalsViolation::Unsorted((20, 30), (10, 40))]);
+
+        let mut intervals = Intervals::<u8>::new();
+
+        intervals.intervals.insert(10, 20);
+        intervals.intervals.insert(30, 30);
+
+        let report = intervals.validate().unwrap_err();
+
+        assert_eq!(report.violations, vec![IntervalsViolation::Inverted((20, 10))]);
+
+        assert_eq!(report.to_string(), "invalid intervals [20,10], [30]\n  [20,10] is inverted");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde()
+    {
+        let intervals: Intervals<u8> = "[0,5], [10], [254,255]".parse().unwrap();
+
+        let json = serde_json::to_string(&intervals).unwrap();
+
+        assert_eq!(json, "[[0,5],[10,10],[254,255]]");
+
+        assert_eq!(serde_json::from_str::<Intervals<u8>>(&json).unwrap().dump(), "[0,5], [10], [254,255]");
+        assert_eq!(serde_json::from_str::<Intervals<u8>>("[\"0-5\", \"6\", [7,9]]").unwrap().dump(), "[0,9]");
+        assert_eq!(serde_json::from_str::<Intervals<u8>>("[]").unwrap().is_empty(), true);
+
+        let parse_error = |json: &str| serde_json::from_str::<Intervals<u8>>(json).err().unwrap().to_string();
+
+        assert_eq!(parse_error("[[10,20],[0,5]]"), "intervals are not sorted");
+        assert_eq!(parse_error("[[10,20],[15,25]]"), "intervals overlap");
+    }
 }
@@ -0,0 +1,132 @@
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::id_type::IdType;
+
+pub struct LiveId<T: IdType> {
+    pub id: T,
+    pub thread: String,
+    pub allocated_at: Instant,
+    pub backtrace: Arc<Backtrace>,
+}
+
+impl<T: IdType> LiveId<T> {
+    pub fn held_for(&self) -> Duration {
+        self.allocated_at.elapsed()
+    }
+}
+
+impl<T: IdType> fmt::Display for LiveId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "id {} allocated by thread {} held for {:?}", self.id, self.thread, self.held_for())?;
+
+        write!(f, "{}", self.backtrace)
+    }
+}
+
+struct AllocationRecord {
+    sequence: u64,
+    thread: String,
+    allocated_at: Instant,
+    backtrace: Arc<Backtrace>,
+}
+
+pub(crate) struct LeakTracker<T: IdType> {
+    live_ids: BTreeMap<T, AllocationRecord>,
+    next_sequence: u64,
+}
+
+impl<T: IdType> LeakTracker<T> {
+    pub fn new() -> Self {
+        LeakTracker { live_ids: BTreeMap::new(), next_sequence: 0 }
+    }
+
+    pub fn allocated(&mut self, id: T) {
+        let current = thread::current();
+
+        let thread = match current.name() {
+            Some(name) => name.to_string(),
+            None => format!("{:?}", current.id()),
+        };
+
+        let record = AllocationRecord {
+            sequence: self.next_sequence,
+            thread,
+            allocated_at: Instant::now(),
+            backtrace: Arc::new(Backtrace::force_capture()),
+        };
+
+        self.next_sequence += 1;
+
+        self.live_ids.insert(id, record);
+    }
+
+    pub fn freed(&mut self, id: T) {
+        self.live_ids.remove(&id);
+    }
+
+    pub fn report(&self, held_for_at_least: Duration) -> Vec<LiveId<T>> {
+        let mut records: Vec<(&T, &AllocationRecord)> = self.live_ids
+            .iter()
+            .filter(|(_, record)| record.allocated_at.elapsed() >= held_for_at_least)
+            .collect();
+
+        records.sort_by_key(|(_, record)| record.sequence);
+
+        records
+            .into_iter()
+            .map(|(id, record)| LiveId {
+                id: *id,
+                thread: record.thread.clone(),
+                allocated_at: record.allocated_at,
+                backtrace: record.backtrace.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut tracker = LeakTracker::<u8>::new();
+
+        tracker.allocated(10);
+        tracker.allocated(5);
+        tracker.allocated(7);
+
+        tracker.freed(5);
+
+        let report = tracker.report(Duration::ZERO);
+
+        let ids: Vec<u8> = report.iter().map(|live_id| live_id.id).collect();
+
+        assert_eq!(ids, vec![10, 7]);
+
+        assert_eq!(tracker.report(Duration::from_secs(3600)).len(), 0);
+    }
+
+    #[test]
+    fn test_report_records_thread() {
+        let mut tracker = LeakTracker::<u8>::new();
+
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .name("allocator".to_string())
+                .spawn_scoped(scope, || tracker.allocated(1))
+                .unwrap();
+        });
+
+        let report = tracker.report(Duration::ZERO);
+
+        assert_eq!(report[0].thread, "allocator");
+
+        assert!(report[0].to_string().starts_with("id 1 allocated by thread allocator held for"));
+    }
+}
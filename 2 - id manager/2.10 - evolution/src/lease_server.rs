@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::id_type::IdType;
+use crate::protocol::{read_frame, write_frame, Request, Response};
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+struct Lease<T: IdType> {
+    upper: T,
+    token: u128,
+    expires: Instant,
+}
+
+// Hands out blocks of ids from a ThreadSafeIdManager to clients connected over a Unix domain
+// socket. Blocks must be renewed before their lease expires, blocks that are not renewed are
+// reclaimed by reap_expired() so that ids held by dead clients are not lost. A block is renewed
+// or returned with the token that it was leased with rather than by the connection that
+// leased it, so that a client that reconnects can carry on.
+
+pub struct LeaseServer<T: IdType> {
+    manager: ThreadSafeIdManager<T>,
+    ttl: Duration,
+    leases: Mutex<BTreeMap<T, Lease<T>>>,
+    next_lease: AtomicU64,
+    token_key: RandomState,
+}
+
+impl<T: IdType> LeaseServer<T> {
+    pub fn new(manager: ThreadSafeIdManager<T>, ttl: Duration) -> Self {
+        LeaseServer {
+            manager,
+            ttl,
+            leases: Mutex::new(BTreeMap::new()),
+            next_lease: AtomicU64::new(0),
+            token_key: RandomState::new(),
+        }
+    }
+
+    pub fn manager(&self) -> &ThreadSafeIdManager<T> {
+        &self.manager
+    }
+
+    pub fn serve(self: &Arc<Self>, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+
+            let server = self.clone();
+
+            thread::spawn(move || server.handle_client(stream));
+        }
+
+        Ok(())
+    }
+
+    pub fn reap_expired(&self, now: Instant) -> usize {
+        let expired: Vec<(T, T)> = {
+            let mut leases = self.leases();
+
+            let expired: Vec<T> = leases
+                .iter()
+                .filter(|(_, lease)| lease.expires <= now)
+                .map(|(lower, _)| *lower)
+                .collect();
+
+            expired.iter().filter_map(|lower| Some((*lower, leases.remove(lower)?.upper))).collect()
+        };
+
+        for (lower, upper) in expired.iter() {
+            self.manager.return_block(*lower, *upper);
+        }
+
+        expired.len()
+    }
+
+    // the leases aren't locked whilst calling the manager as it calls its observers
+
+    fn leases(&self) -> MutexGuard<'_, BTreeMap<T, Lease<T>>> {
+        self.leases.lock().unwrap()
+    }
+
+    // the count makes each token unique and the keyed hash makes another client's token hard
+    // to guess
+
+    fn new_token(&self) -> u128 {
+        let count = self.next_lease.fetch_add(1, Ordering::Relaxed);
+
+        ((count as u128) << 64) | self.token_key.hash_one(count) as u128
+    }
+
+    fn handle_client(&self, mut stream: UnixStream) {
+        // the client's leases are left to expire when it goes away, it may be that the
+        // connection was lost whilst the client is still using its ids
+
+        while let Ok(message) = read_frame(&mut stream) {
+            let response = match Request::decode(&message) {
+                Ok(request) => self.handle_request(request, Instant::now()),
+                Err(reason) => Response::Error(reason),
+            };
+
+            if write_frame(&mut stream, &response.encode()).is_err() {
+                break;
+            }
+        }
+    }
+
+    pub(crate) fn handle_request(&self, request: Request, now: Instant) -> Response {
+        match self.try_handle_request(request, now) {
+            Ok(response) => response,
+            Err(reason) => Response::Error(reason),
+        }
+    }
+
+    fn try_handle_request(&self, request: Request, now: Instant) -> Result<Response, String> {
+        match request {
+            Request::Lease(size) => {
+                if size == 0 {
+                    return Err("size must be > 0".to_string());
+                }
+
+                let Some((lower, upper)) = self.manager.lease_block(size) else {
+                    return Ok(Response::Unavailable);
+                };
+
+                let token = self.new_token();
+
+                self.leases().insert(lower, Lease { upper, token, expires: now + self.ttl });
+
+                Ok(Response::Leased(lower.to_u128(), upper.to_u128(), token))
+            }
+            Request::Renew(lower, upper, token) => {
+                let mut leases = self.leases();
+
+                let lease = Self::find_lease(&mut leases, lower, upper, token)?;
+
+                lease.expires = now + self.ttl;
+
+                Ok(Response::Ok)
+            }
+            Request::Return(lower, upper, token) => {
+                let lower = {
+                    let mut leases = self.leases();
+
+                    Self::find_lease(&mut leases, lower, upper, token)?;
+
+                    let lower = to_id::<T>(lower)?;
+
+                    leases.remove(&lower);
+
+                    lower
+                };
+
+                self.manager.return_block(lower, to_id::<T>(upper)?);
+
+                Ok(Response::Ok)
+            }
+        }
+    }
+
+    fn find_lease(leases: &mut BTreeMap<T, Lease<T>>, lower: u128, upper: u128, token: u128) -> Result<&mut Lease<T>, String> {
+        let lease = leases.get_mut(&to_id::<T>(lower)?);
+
+        match lease {
+            Some(lease) if lease.token == token && lease.upper.to_u128() == upper => Ok(lease),
+            _ => Err(format!("block {}-{} is not leased", lower, upper)),
+        }
+    }
+}
+
+fn to_id<T: IdType>(value: u128) -> Result<T, String> {
+    if value > T::MAX.to_u128() {
+        return Err(format!("invalid value: {}", value));
+    }
+
+    Ok(T::from_u128(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::observer::IdManagerEvent;
+    use crate::reuse_policy::ReusePolicy::ReuseFast;
+
+    use super::*;
+
+    fn new_server() -> LeaseServer<u8> {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+
+        LeaseServer::new(manager, Duration::from_secs(10))
+    }
+
+    fn lease(server: &LeaseServer<u8>, size: u128, now: Instant) -> (u128, u128, u128) {
+        match server.handle_request(Request::Lease(size), now) {
+            Response::Leased(lower, upper, token) => (lower, upper, token),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn test_lease_and_return() {
+        let server = new_server();
+
+        let now = Instant::now();
+
+        let (lower, upper, token) = lease(&server, 5, now);
+
+        assert_eq!((lower, upper), (10, 14));
+        assert_eq!(lease(&server, 5, now).0, 15);
+
+        assert_eq!(server.handle_request(Request::Lease(32), now), Response::Unavailable);
+        assert_eq!(server.handle_request(Request::Lease(0), now), Response::Error("size must be > 0".to_string()));
+
+        assert_eq!(server.manager().dump(), "[20,50]");
+
+        assert_eq!(server.handle_request(Request::Return(10, 14, token + 1), now), Response::Error("block 10-14 is not leased".to_string()));
+        assert_eq!(server.handle_request(Request::Return(10, 13, token), now), Response::Error("block 10-13 is not leased".to_string()));
+        assert_eq!(server.handle_request(Request::Return(300, 301, token), now), Response::Error("invalid value: 300".to_string()));
+        assert_eq!(server.handle_request(Request::Return(10, 14, token), now), Response::Ok);
+        assert_eq!(server.handle_request(Request::Return(10, 14, token), now), Response::Error("block 10-14 is not leased".to_string()));
+
+        assert_eq!(server.manager().dump(), "[10,14], [20,50]");
+    }
+
+    #[test]
+    fn test_tokens_are_unique() {
+        let server = new_server();
+
+        let now = Instant::now();
+
+        let (lower, upper, token) = lease(&server, 5, now);
+
+        assert_eq!(server.handle_request(Request::Return(lower, upper, token), now), Response::Ok);
+
+        // the same block leased again has a new token
+
+        let (_, _, other_token) = lease(&server, 5, now);
+
+        assert_ne!(token, other_token);
+
+        assert_eq!(server.handle_request(Request::Renew(lower, upper, token), now), Response::Error("block 10-14 is not leased".to_string()));
+        assert_eq!(server.handle_request(Request::Renew(lower, upper, other_token), now), Response::Ok);
+    }
+
+    #[test]
+    fn test_reap_expired() {
+        let server = new_server();
+
+        let now = Instant::now();
+
+        let (_, _, first) = lease(&server, 5, now);
+        let (_, _, second) = lease(&server, 5, now);
+
+        assert_eq!(server.reap_expired(now + Duration::from_secs(5)), 0);
+
+        assert_eq!(server.handle_request(Request::Renew(10, 14, first), now + Duration::from_secs(5)), Response::Ok);
+        assert_eq!(server.handle_request(Request::Renew(15, 19, first), now + Duration::from_secs(5)), Response::Error("block 15-19 is not leased".to_string()));
+
+        assert_eq!(server.reap_expired(now + Duration::from_secs(10)), 1);
+
+        assert_eq!(server.manager().dump(), "[15,50]");
+
+        assert_eq!(server.handle_request(Request::Renew(15, 19, second), now + Duration::from_secs(10)), Response::Error("block 15-19 is not leased".to_string()));
+
+        assert_eq!(server.reap_expired(now + Duration::from_secs(15)), 1);
+
+        assert_eq!(server.manager().dump(), "[10,50]");
+    }
+
+    #[test]
+    fn test_observers_can_call_back_into_the_server() {
+        let server = Arc::new(new_server());
+
+        let observed = Arc::downgrade(&server);
+
+        // an observer that looks at the leases would deadlock if they were locked whilst the
+        // manager is called
+
+        server.manager().add_observer(move |_: &IdManagerEvent<u8>| {
+            if let Some(server) = observed.upgrade() {
+                server.reap_expired(Instant::now());
+            }
+        });
+
+        let (lower, upper, token) = lease(&server, 5, Instant::now());
+
+        assert_eq!(server.handle_request(Request::Return(lower, upper, token), Instant::now()), Response::Ok);
+    }
+}
@@ -1,14 +1,73 @@
 #![allow(dead_code)]
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
 
 mod interval;
+mod interval_error;
 mod intervals;
+mod interval_map;
 mod id_manager;
 mod smart_id;
 mod thread_safe_id_manager;
 mod id_type;
+mod step;
 mod reuse_policy;
+mod observer;
+mod id_manager_stats;
+mod capacity_threshold;
+mod leak_tracker;
+mod range_error;
+mod range_order;
+mod block_source;
+mod parse_error;
+mod snapshot;
+mod validation_report;
+mod sync;
+mod trace;
+mod ttl_lease;
+mod free_set;
+mod bitmap_free_set;
+mod hybrid_free_set;
+mod address_pool;
+mod port_allocator;
+#[cfg(unix)]
+mod protocol;
+#[cfg(unix)]
+mod lease_server;
+#[cfg(unix)]
+mod remote_id_manager;
+#[cfg(test)]
+mod model_tests;
+#[cfg(all(test, loom))]
+mod loom_tests;
 
 pub use thread_safe_id_manager::ThreadSafeIdManager as IdManager;
 pub use smart_id::SmartId as Id;
+pub use ttl_lease::{LeaseReaper, TtlLease};
+pub use id_manager::IdManager as UnsyncIdManager;
 pub use id_type::IdType;
+pub use step::Step;
 pub use reuse_policy::ReusePolicy;
+pub use observer::{IdManagerEvent, IdManagerObserver, ObserverId};
+pub use id_manager_stats::IdManagerStats;
+pub use capacity_threshold::{CapacityAlert, CapacityThreshold};
+pub use leak_tracker::LiveId;
+pub use range_error::RangeError;
+pub use range_order::RangeOrder;
+pub use interval::{Interval, Values};
+pub use interval_error::IntervalError;
+pub use intervals::Intervals;
+pub use interval_map::IntervalMap;
+pub use free_set::FreeSet;
+pub use bitmap_free_set::BitmapFreeSet;
+pub use hybrid_free_set::HybridFreeSet;
+pub use address_pool::{AddressLease, AddressPool, PoolAddress, ReleasedAddress};
+pub use port_allocator::{PortAllocator, PortLease, ReleasedPort};
+pub use block_source::BlockSource;
+pub use parse_error::ParseError;
+pub use snapshot::IdManagerSnapshot;
+pub use trace::{Trace, TraceOp};
+pub use validation_report::{IntervalsViolation, ValidationReport};
+#[cfg(unix)]
+pub use lease_server::LeaseServer;
+#[cfg(unix)]
+pub use remote_id_manager::{RemoteBlockSource, RemoteIdManager};
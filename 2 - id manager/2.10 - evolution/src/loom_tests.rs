@@ -0,0 +1,197 @@
+// Explores the interleavings of threads sharing a ThreadSafeIdManager, run with:
+//
+//   RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests
+
+use std::collections::BTreeSet;
+
+use loom::sync::{Arc, Mutex};
+use loom::thread;
+
+use crate::intervals::Intervals;
+use crate::reuse_policy::ReusePolicy;
+use crate::reuse_policy::ReusePolicy::{ReuseFast, ReuseSlow};
+use crate::smart_id::SmartId;
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+fn check<F: Fn() + Sync + Send + 'static>(test: F) {
+    let mut builder = loom::model::Builder::new();
+
+    builder.preemption_bound = Some(3);
+
+    builder.check(test);
+}
+
+// the ids that are currently held, an id being handed out twice is caught as soon as it happens
+
+#[derive(Clone)]
+struct Held(Arc<Mutex<BTreeSet<u8>>>);
+
+impl Held {
+    fn new() -> Self {
+        Held(Arc::new(Mutex::new(BTreeSet::new())))
+    }
+
+    fn take(&self, id: &SmartId<u8>) {
+        assert!(self.0.lock().unwrap().insert(*id.value()), "{} was handed out twice", id);
+    }
+
+    fn give_back(&self, id: SmartId<u8>) {
+        self.0.lock().unwrap().remove(id.value());
+
+        drop(id);
+    }
+}
+
+fn dump_without(lower: u8, upper: u8, used: &[u8]) -> String {
+    let mut intervals = Intervals::<u8>::new();
+
+    intervals.insert_interval(lower, upper);
+
+    for id in used {
+        intervals.remove_value(*id);
+    }
+
+    intervals.dump()
+}
+
+fn concurrent_allocate_id(reuse_policy: ReusePolicy) {
+    check(move || {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(reuse_policy, 0, 1);
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let manager = manager.clone();
+
+                thread::spawn(move || manager.allocate_id())
+            })
+            .collect();
+
+        let ids: Vec<SmartId<u8>> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+
+        assert_ne!(ids[0].value(), ids[1].value());
+
+        assert_eq!(manager.dump(), "");
+
+        drop(ids);
+
+        assert_eq!(manager.dump(), "[0,1]");
+    });
+}
+
+#[test]
+fn test_concurrent_allocate_id_reuse_fast() {
+    concurrent_allocate_id(ReuseFast);
+}
+
+#[test]
+fn test_concurrent_allocate_id_reuse_slow() {
+    concurrent_allocate_id(ReuseSlow);
+}
+
+fn allocate_while_dropping(reuse_policy: ReusePolicy) {
+    check(move || {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(reuse_policy, 0, 2);
+
+        let held = Held::new();
+
+        let first = manager.allocate_id();
+
+        held.take(&first);
+
+        let dropping = {
+            let held = held.clone();
+
+            thread::spawn(move || held.give_back(first))
+        };
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let manager = manager.clone();
+
+                let held = held.clone();
+
+                thread::spawn(move || {
+                    let id = manager.allocate_id();
+
+                    held.take(&id);
+
+                    held.give_back(id);
+                })
+            })
+            .collect();
+
+        dropping.join().unwrap();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(manager.dump(), "[0,2]");
+    });
+}
+
+#[test]
+fn test_allocate_while_dropping_reuse_fast() {
+    allocate_while_dropping(ReuseFast);
+}
+
+#[test]
+fn test_allocate_while_dropping_reuse_slow() {
+    allocate_while_dropping(ReuseSlow);
+}
+
+fn release_while_marking(reuse_policy: ReusePolicy) {
+    check(move || {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(reuse_policy, 0, 2);
+
+        let held = Held::new();
+
+        let releasing = {
+            let manager = manager.clone();
+
+            let held = held.clone();
+
+            thread::spawn(move || {
+                let mut id = manager.allocate_id();
+
+                held.take(&id);
+
+                id.release()
+            })
+        };
+
+        let marking = {
+            let manager = manager.clone();
+
+            let held = held.clone();
+
+            thread::spawn(move || {
+                manager.mark_value_as_used(2);
+
+                let id = manager.allocate_id();
+
+                assert_ne!(*id.value(), 2);
+
+                held.take(&id);
+
+                held.give_back(id);
+            })
+        };
+
+        let released = releasing.join().unwrap();
+
+        marking.join().unwrap();
+
+        assert_eq!(manager.dump(), dump_without(0, 2, &[released, 2]));
+    });
+}
+
+#[test]
+fn test_release_while_marking_reuse_fast() {
+    release_while_marking(ReuseFast);
+}
+
+#[test]
+fn test_release_while_marking_reuse_slow() {
+    release_while_marking(ReuseSlow);
+}
@@ -0,0 +1,447 @@
+// Drives Intervals and IdManager with random sequences of operations and compares them against
+// a naive BTreeSet model. u8 is checked value by value over its whole range, wider types are
+// sampled from windows at either end of their range so that merging, splitting and overflow at
+// T::MAX are all exercised.
+
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+
+use proptest::prelude::*;
+
+use crate::bitmap_free_set::BitmapFreeSet;
+use crate::free_set::FreeSet;
+use crate::hybrid_free_set::HybridFreeSet;
+use crate::id_manager::IdManager;
+use crate::id_type::IdType;
+use crate::intervals::Intervals;
+use crate::reuse_policy::ReusePolicy;
+
+const WINDOW: u128 = 300;
+const MAX_OPS: usize = 64;
+const MAX_INTERVAL_LEN: u128 = 40;
+
+#[derive(Debug, Clone)]
+enum IntervalsOp<T> {
+    Insert(T, T),
+    Remove(T, T),
+    RemoveValue(T),
+    RemoveFirstValue,
+    RemoveFirstValueIn(T, T),
+    Union(Vec<(T, T)>),
+    Contains(T, T),
+}
+
+#[derive(Debug, Clone)]
+enum IdManagerOp<T> {
+    Allocate,
+    Free(usize),
+    AllocateBlock(u128),
+    FreeBlock(usize),
+    MarkValue(T),
+    MarkInterval(T, T),
+}
+
+fn values<T: IdType + Debug>() -> BoxedStrategy<T> {
+    let max = T::MAX.to_u128();
+
+    if max <= WINDOW {
+        (0..=max).prop_map(T::from_u128).boxed()
+    } else {
+        prop_oneof![0..WINDOW, (max - WINDOW)..=max].prop_map(T::from_u128).boxed()
+    }
+}
+
+fn intervals<T: IdType + Debug>() -> impl Strategy<Value = (T, T)> {
+    intervals_up_to(MAX_INTERVAL_LEN)
+}
+
+fn intervals_up_to<T: IdType + Debug>(max_len: u128) -> impl Strategy<Value = (T, T)> {
+    (values::<T>(), 0..max_len).prop_map(|(lower, len)| {
+        let upper = lower.to_u128().saturating_add(len).min(T::MAX.to_u128());
+
+        (lower, T::from_u128(upper))
+    })
+}
+
+fn intervals_ops<T: IdType + Debug>(max_ops: usize) -> impl Strategy<Value = Vec<IntervalsOp<T>>> {
+    let op = prop_oneof![
+        4 => intervals::<T>().prop_map(|(lower, upper)| IntervalsOp::Insert(lower, upper)),
+        2 => intervals::<T>().prop_map(|(lower, upper)| IntervalsOp::Remove(lower, upper)),
+        2 => values::<T>().prop_map(IntervalsOp::RemoveValue),
+        1 => Just(IntervalsOp::RemoveFirstValue),
+        1 => intervals::<T>().prop_map(|(lower, upper)| IntervalsOp::RemoveFirstValueIn(lower, upper)),
+        1 => prop::collection::vec(intervals::<T>(), 0..5).prop_map(IntervalsOp::Union),
+        1 => intervals::<T>().prop_map(|(lower, upper)| IntervalsOp::Contains(lower, upper)),
+    ];
+
+    prop::collection::vec(op, 0..max_ops)
+}
+
+fn id_manager_ops<T: IdType + Debug>(max_ops: usize) -> impl Strategy<Value = Vec<IdManagerOp<T>>> {
+    let op = prop_oneof![
+        4 => Just(IdManagerOp::Allocate),
+        2 => any::<usize>().prop_map(IdManagerOp::Free),
+        1 => (1..8u128).prop_map(IdManagerOp::AllocateBlock),
+        1 => any::<usize>().prop_map(IdManagerOp::FreeBlock),
+        1 => values::<T>().prop_map(IdManagerOp::MarkValue),
+        1 => intervals::<T>().prop_map(|(lower, upper)| IdManagerOp::MarkInterval(lower, upper)),
+    ];
+
+    prop::collection::vec(op, 0..max_ops)
+}
+
+fn range_of<T: IdType>(lower: T, upper: T) -> impl Iterator<Item = T> {
+    (lower.to_u128()..=upper.to_u128()).map(T::from_u128)
+}
+
+fn check_invariants<T: IdType>(intervals: &Intervals<T>, min: T, max: T) -> Result<(), TestCaseError> {
+    let mut previous: Option<T> = None;
+
+    for interval in intervals.iter() {
+        prop_assert!(interval.lower() <= interval.upper(), "{} is inverted", interval);
+        prop_assert!(interval.lower() >= min && interval.upper() <= max, "{} is out of bounds", interval);
+
+        if let Some(previous) = previous {
+            prop_assert!(previous < interval.lower(), "{} is not sorted: {}", interval, intervals);
+            prop_assert!(previous + T::one() < interval.lower(), "{} is not merged: {}", interval, intervals);
+        }
+
+        previous = Some(interval.upper());
+    }
+
+    Ok(())
+}
+
+fn check_matches_model<T: IdType + Debug>(intervals: &Intervals<T>, model: &BTreeSet<T>) -> Result<(), TestCaseError> {
+    let mut values = BTreeSet::new();
+
+    for interval in intervals.iter() {
+        values.extend(range_of(interval.lower(), interval.upper()));
+    }
+
+    prop_assert_eq!(&values, model, "{}", intervals);
+
+    prop_assert_eq!(intervals.value_count(), model.len() as u128);
+    prop_assert_eq!(intervals.interval_count() == 0, model.is_empty());
+
+    if T::MAX.to_u128() <= WINDOW {
+        for value in range_of(T::MIN, T::MAX) {
+            prop_assert_eq!(intervals.contains_value(value), model.contains(&value), "{:?} in {}", value, intervals);
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_into_model<T: IdType>(model: &mut BTreeSet<T>, lower: T, upper: T) -> bool {
+    if model.range(lower..=upper).next().is_some() {
+        return false;
+    }
+
+    model.extend(range_of(lower, upper));
+
+    true
+}
+
+fn run_free_set<T: IdType + Debug, S: FreeSet<T>>(ops: Vec<IntervalsOp<T>>) -> Result<(), TestCaseError> {
+    let mut free_set = S::from_intervals(&Intervals::new());
+
+    let mut model = BTreeSet::new();
+
+    for op in ops {
+        match op.clone() {
+            IntervalsOp::Insert(lower, upper) => {
+                prop_assert_eq!(free_set.insert_interval(lower, upper), insert_into_model(&mut model, lower, upper), "{:?}", op);
+            }
+            IntervalsOp::Remove(lower, upper) => {
+                let removed: Vec<T> = model.range(lower..=upper).copied().collect();
+
+                for value in removed.iter() {
+                    model.remove(value);
+                }
+
+                prop_assert_eq!(free_set.remove_interval(lower, upper), removed.len() as u128, "{:?}", op);
+            }
+            IntervalsOp::RemoveValue(value) => {
+                prop_assert_eq!(free_set.remove_value(value), model.remove(&value), "{:?}", op);
+            }
+            IntervalsOp::RemoveFirstValue => {
+                if let Some(first) = model.pop_first() {
+                    prop_assert_eq!(free_set.remove_first_value(), Some(first));
+                }
+            }
+            IntervalsOp::RemoveFirstValueIn(lower, upper) => {
+                let first = model.range(lower..=upper).next().copied();
+
+                if let Some(first) = first {
+                    model.remove(&first);
+                }
+
+                prop_assert_eq!(free_set.remove_first_value_in(lower, upper), first, "{:?}", op);
+            }
+            IntervalsOp::Union(others) => {
+                let mut other = Intervals::<T>::new();
+
+                let mut other_model = BTreeSet::new();
+
+                for (lower, upper) in others {
+                    prop_assert_eq!(other.insert_interval(lower, upper), insert_into_model(&mut other_model, lower, upper));
+                }
+
+                free_set.union(&other);
+
+                model.extend(other_model);
+            }
+            IntervalsOp::Contains(lower, upper) => {
+                let expected = range_of(lower, upper).all(|value| model.contains(&value));
+
+                prop_assert_eq!(free_set.contains_interval(lower, upper), expected, "{:?}", op);
+            }
+        }
+
+        let intervals = free_set.to_intervals();
+
+        check_invariants(&intervals, T::MIN, T::MAX)?;
+        check_matches_model(&intervals, &model)?;
+
+        prop_assert_eq!(free_set.value_count(), intervals.value_count());
+        prop_assert_eq!(free_set.interval_count(), intervals.interval_count());
+        prop_assert_eq!(free_set.largest_interval_len(), intervals.largest_interval_len());
+
+        prop_assert_eq!(free_set.dump().parse::<Intervals<T>>().map(|parsed| parsed.dump()), Ok(free_set.dump()));
+    }
+
+    Ok(())
+}
+
+struct IdManagerModel<T: IdType> {
+    reuse_policy: ReusePolicy,
+    min: T,
+    max: T,
+    free: BTreeSet<T>,
+    next_to_allocate: T,
+    wrap_arounds: u64,
+}
+
+impl<T: IdType> IdManagerModel<T> {
+    fn allocate(&mut self) -> Option<T> {
+        if self.free.is_empty() {
+            return None;
+        }
+
+        if self.reuse_policy == ReusePolicy::ReuseFast {
+            return self.free.pop_first();
+        }
+
+        loop {
+            let id = self.next_to_allocate;
+
+            if id == self.max {
+                self.next_to_allocate = self.min;
+
+                self.wrap_arounds += 1;
+            } else {
+                self.next_to_allocate = id + T::one();
+            }
+
+            if self.free.remove(&id) {
+                return Some(id);
+            }
+        }
+    }
+
+    fn allocate_block(&mut self, size: u128) -> Option<(T, T)> {
+        let lower = self.free.iter().copied().find(|lower| match lower.to_u128().checked_add(size - 1) {
+            Some(upper) => upper <= self.max.to_u128() && range_of(*lower, T::from_u128(upper)).all(|value| self.free.contains(&value)),
+            None => false,
+        })?;
+
+        let upper = T::from_u128(lower.to_u128() + (size - 1));
+
+        for value in range_of(lower, upper) {
+            self.free.remove(&value);
+        }
+
+        Some((lower, upper))
+    }
+}
+
+fn run_id_manager<T: IdType + Debug, S: FreeSet<T>>(reuse_policy: ReusePolicy, (min, max): (T, T), ops: Vec<IdManagerOp<T>>) -> Result<(), TestCaseError> {
+    let mut manager = IdManager::<T, S>::new_limited_range(reuse_policy, min, max);
+
+    let mut model = IdManagerModel {
+        reuse_policy,
+        min,
+        max,
+        free: range_of(min, max).collect(),
+        next_to_allocate: min,
+        wrap_arounds: 0,
+    };
+
+    let capacity = max.to_u128() - min.to_u128() + 1;
+
+    let mut ids = Vec::new();
+    let mut blocks = Vec::new();
+
+    let in_range = |value: T| T::from_u128(min.to_u128() + (value.to_u128() - T::MIN.to_u128()) % capacity);
+
+    for op in ops {
+        match op.clone() {
+            IdManagerOp::Allocate => {
+                let expected = model.allocate();
+
+                prop_assert_eq!(manager.can_allocate(), expected.is_some());
+
+                if let Some(expected) = expected {
+                    prop_assert_eq!(manager.allocate(), expected);
+
+                    ids.push(expected);
+                }
+            }
+            IdManagerOp::Free(index) => {
+                if !ids.is_empty() {
+                    let id = ids.swap_remove(index % ids.len());
+
+                    manager.free(id);
+
+                    model.free.insert(id);
+                }
+            }
+            IdManagerOp::AllocateBlock(size) => {
+                let expected = model.allocate_block(size);
+
+                prop_assert_eq!(manager.allocate_block(size), expected, "{:?}", op);
+
+                blocks.extend(expected);
+            }
+            IdManagerOp::FreeBlock(index) => {
+                if !blocks.is_empty() {
+                    let (lower, upper) = blocks.swap_remove(index % blocks.len());
+
+                    manager.free_block(lower, upper);
+
+                    model.free.extend(range_of(lower, upper));
+                }
+            }
+            IdManagerOp::MarkValue(value) => {
+                let id = in_range(value);
+
+                manager.mark_value_as_used(id);
+
+                if model.free.remove(&id) {
+                    ids.push(id);
+                }
+            }
+            IdManagerOp::MarkInterval(lower, upper) => {
+                let (lower, upper) = (in_range(lower), in_range(upper));
+
+                let (lower, upper) = (lower.min(upper), lower.max(upper));
+
+                manager.mark_interval_as_used(lower, upper);
+
+                for id in range_of(lower, upper) {
+                    if model.free.remove(&id) {
+                        ids.push(id);
+                    }
+                }
+            }
+        }
+
+        let snapshot = manager.snapshot();
+
+        check_invariants(&snapshot.free_ids, min, max)?;
+        check_matches_model(&snapshot.free_ids, &model.free)?;
+
+        let stats = manager.stats();
+
+        prop_assert_eq!(stats.capacity, capacity);
+        prop_assert_eq!(stats.allocated, capacity - model.free.len() as u128);
+
+        if reuse_policy == ReusePolicy::ReuseSlow {
+            prop_assert_eq!(snapshot.next_to_allocate, model.next_to_allocate);
+            prop_assert_eq!(manager.wrap_arounds(), model.wrap_arounds);
+        }
+    }
+
+    Ok(())
+}
+
+fn id_ranges<T: IdType + Debug>() -> impl Strategy<Value = (T, T)> {
+    intervals_up_to(WINDOW)
+}
+
+fn reuse_policies() -> impl Strategy<Value = ReusePolicy> {
+    prop_oneof![Just(ReusePolicy::ReuseFast), Just(ReusePolicy::ReuseSlow)]
+}
+
+proptest! {
+    #[test]
+    fn intervals_u8(ops in intervals_ops::<u8>(MAX_OPS)) {
+        run_free_set::<u8, Intervals<u8>>(ops)?;
+    }
+
+    #[test]
+    fn intervals_u16(ops in intervals_ops::<u16>(MAX_OPS)) {
+        run_free_set::<u16, Intervals<u16>>(ops)?;
+    }
+
+    #[test]
+    fn intervals_u64(ops in intervals_ops::<u64>(MAX_OPS)) {
+        run_free_set::<u64, Intervals<u64>>(ops)?;
+    }
+
+    #[test]
+    fn intervals_u128(ops in intervals_ops::<u128>(MAX_OPS)) {
+        run_free_set::<u128, Intervals<u128>>(ops)?;
+    }
+
+    #[test]
+    fn bitmap_u8(ops in intervals_ops::<u8>(MAX_OPS)) {
+        run_free_set::<u8, BitmapFreeSet<u8>>(ops)?;
+    }
+
+    #[test]
+    fn bitmap_u16(ops in intervals_ops::<u16>(MAX_OPS)) {
+        run_free_set::<u16, BitmapFreeSet<u16>>(ops)?;
+    }
+
+    #[test]
+    fn hybrid_u8(ops in intervals_ops::<u8>(MAX_OPS * 2)) {
+        run_free_set::<u8, HybridFreeSet<u8>>(ops)?;
+    }
+
+    #[test]
+    fn hybrid_u128(ops in intervals_ops::<u128>(MAX_OPS * 2)) {
+        run_free_set::<u128, HybridFreeSet<u128>>(ops)?;
+    }
+
+    #[test]
+    fn id_manager_u8(reuse_policy in reuse_policies(), range in id_ranges::<u8>(), ops in id_manager_ops::<u8>(MAX_OPS)) {
+        run_id_manager::<u8, Intervals<u8>>(reuse_policy, range, ops)?;
+    }
+
+    #[test]
+    fn id_manager_u32(reuse_policy in reuse_policies(), range in id_ranges::<u32>(), ops in id_manager_ops::<u32>(MAX_OPS)) {
+        run_id_manager::<u32, Intervals<u32>>(reuse_policy, range, ops)?;
+    }
+
+    #[test]
+    fn id_manager_u128(reuse_policy in reuse_policies(), range in id_ranges::<u128>(), ops in id_manager_ops::<u128>(MAX_OPS)) {
+        run_id_manager::<u128, Intervals<u128>>(reuse_policy, range, ops)?;
+    }
+
+    #[test]
+    fn id_manager_bitmap_u8(reuse_policy in reuse_policies(), range in id_ranges::<u8>(), ops in id_manager_ops::<u8>(MAX_OPS)) {
+        run_id_manager::<u8, BitmapFreeSet<u8>>(reuse_policy, range, ops)?;
+    }
+
+    #[test]
+    fn id_manager_bitmap_u16(reuse_policy in reuse_policies(), range in id_ranges::<u16>(), ops in id_manager_ops::<u16>(MAX_OPS)) {
+        run_id_manager::<u16, BitmapFreeSet<u16>>(reuse_policy, range, ops)?;
+    }
+
+    #[test]
+    fn id_manager_hybrid_u128(reuse_policy in reuse_policies(), range in id_ranges::<u128>(), ops in id_manager_ops::<u128>(MAX_OPS * 2)) {
+        run_id_manager::<u128, HybridFreeSet<u128>>(reuse_policy, range, ops)?;
+    }
+}
@@ -0,0 +1,37 @@
+use crate::id_type::IdType;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IdManagerEvent<T: IdType> {
+    // a reserved id is notified as Allocated once it has been committed
+    Allocated(T),
+    Freed(T),
+    Expired(T),
+    Reserved(T),
+    Aborted(T),
+    MarkedAsUsed(T, T),
+    BlockAllocated(T, T),
+    BlockFreed(T, T),
+    Exhausted,
+    WrappedAround,
+}
+
+// Observers are always called after the manager's lock has been released, so it is safe
+// for an observer to call back into the manager that notified it.
+
+// Returned by add_observer() so that the observer can later be removed
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ObserverId(pub(crate) u64);
+
+pub trait IdManagerObserver<T: IdType>: Send + Sync {
+    fn on_event(&self, event: &IdManagerEvent<T>);
+}
+
+impl<T: IdType, F> IdManagerObserver<T> for F
+where
+    F: Fn(&IdManagerEvent<T>) + Send + Sync,
+{
+    fn on_event(&self, event: &IdManagerEvent<T>) {
+        self(event)
+    }
+}
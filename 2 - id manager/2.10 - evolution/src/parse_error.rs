@@ -0,0 +1,27 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    InvalidFormat(String),
+    InvalidValue(String),
+    UpperBelowLower(String),
+    Unsorted,
+    Overlapping,
+    OutOfRange,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidFormat(text) => write!(f, "invalid format: {}", text),
+            ParseError::InvalidValue(text) => write!(f, "invalid value: {}", text),
+            ParseError::UpperBelowLower(text) => write!(f, "upper must be >= lower: {}", text),
+            ParseError::Unsorted => write!(f, "intervals are not sorted"),
+            ParseError::Overlapping => write!(f, "intervals overlap"),
+            ParseError::OutOfRange => write!(f, "value is out of range"),
+        }
+    }
+}
+
+impl Error for ParseError {}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::net::{IpAddr, TcpListener};
+
+use crate::reuse_policy::ReusePolicy;
+use crate::smart_id::SmartId;
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+// port 0 asks the OS for any port and the rest need privileges to bind
+const WELL_KNOWN_PORTS: (u16, u16) = (0, 1023);
+
+// Hands out port numbers, e.g. for tests that need servers on distinct ports. ReuseSlow
+// avoids handing out a port that was just freed and may still be in TIME_WAIT. With the bind
+// probe enabled, ports that something outside of the allocator is already listening on are
+// skipped.
+
+#[derive(Clone)]
+pub struct PortAllocator {
+    manager: ThreadSafeIdManager<u16>,
+    probe_address: Option<IpAddr>,
+}
+
+impl PortAllocator {
+    pub fn new(reuse_policy: ReusePolicy, min_port: u16, max_port: u16) -> Self {
+        let manager = ThreadSafeIdManager::new_limited_range(reuse_policy, min_port, max_port);
+
+        let allocator = PortAllocator { manager, probe_address: None };
+
+        allocator.reserve(WELL_KNOWN_PORTS.0, WELL_KNOWN_PORTS.1);
+
+        allocator
+    }
+
+    // marks the ports in [lower, upper] that are within the allocator's range as used
+
+    pub fn reserve(&self, lower: u16, upper: u16) {
+        let snapshot = self.manager.snapshot();
+
+        let (Some(first), Some(last)) = (snapshot.ranges.iter().next(), snapshot.ranges.iter().next_back()) else {
+            return;
+        };
+
+        let lower = lower.max(first.lower());
+
+        let upper = upper.min(last.upper());
+
+        if lower <= upper {
+            self.manager.mark_interval_as_used(lower, upper);
+        }
+    }
+
+    // checks that each port can be bound on address before handing it out
+
+    pub fn enable_bind_probe(&mut self, address: IpAddr) {
+        self.probe_address = Some(address);
+    }
+
+    // returns None once every port is in use
+
+    pub fn allocate(&self) -> Option<PortLease> {
+        // ports that fail the probe are held until a port is found so that they aren't
+        // allocated again by this call
+
+        let mut unavailable = Vec::new();
+
+        loop {
+            let id = self.manager.try_allocate_id()?;
+
+            match self.probe_address {
+                Some(address) if TcpListener::bind((address, *id.value())).is_err() => unavailable.push(id),
+                _ => return Some(PortLease { id }),
+            }
+        }
+    }
+
+    // frees a port that was kept with PortLease::release
+
+    pub fn free(&self, released: ReleasedPort) {
+        self.manager.free(released.port);
+    }
+
+    pub fn available(&self) -> u128 {
+        self.manager.stats().available()
+    }
+
+    pub fn dump(&self) -> String {
+        self.manager.dump()
+    }
+}
+
+// A port allocated from a PortAllocator, returned to the allocator when dropped
+
+pub struct PortLease {
+    id: SmartId<u16>,
+}
+
+impl PortLease {
+    pub fn port(&self) -> u16 {
+        *self.id.value()
+    }
+
+    // keeps the port allocated, it must then be returned with PortAllocator::free
+
+    pub fn release(mut self) -> ReleasedPort {
+        ReleasedPort { port: self.id.release() }
+    }
+}
+
+impl fmt::Display for PortLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.port())
+    }
+}
+
+// A port that is still allocated after its lease was released, only PortLease::release
+// creates one
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReleasedPort {
+    port: u16,
+}
+
+impl ReleasedPort {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl fmt::Display for ReleasedPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::reuse_policy::ReusePolicy::{ReuseFast, ReuseSlow};
+
+    use super::*;
+
+    #[test]
+    fn test_well_known_ports_are_reserved() {
+        let allocator = PortAllocator::new(ReuseSlow, 0, 2000);
+
+        assert_eq!(allocator.dump(), "[1024,2000]");
+
+        let allocator = PortAllocator::new(ReuseSlow, 49152, 65535);
+
+        assert_eq!(allocator.dump(), "[49152,65535]");
+    }
+
+    #[test]
+    fn test_reserve() {
+        let allocator = PortAllocator::new(ReuseSlow, 8000, 8100);
+
+        allocator.reserve(8080, 8080);
+        allocator.reserve(7000, 8009);
+        allocator.reserve(8090, 9000);
+        allocator.reserve(100, 200);
+
+        assert_eq!(allocator.dump(), "[8010,8079], [8081,8089]");
+        assert_eq!(allocator.available(), 79);
+    }
+
+    #[test]
+    fn test_allocate() {
+        let allocator = PortAllocator::new(ReuseSlow, 8000, 8002);
+
+        let first = allocator.allocate().unwrap();
+
+        assert_eq!(first.port(), 8000);
+        assert_eq!(first.to_string(), "8000");
+
+        let second = allocator.allocate().unwrap();
+
+        drop(first);
+
+        // ReuseSlow doesn't hand out the port that was just freed
+
+        assert_eq!(allocator.allocate().unwrap().port(), 8002);
+
+        let released = second.release();
+
+        assert_eq!(released.port(), 8001);
+        assert_eq!(allocator.dump(), "[8000], [8002]");
+
+        allocator.free(released);
+
+        assert_eq!(allocator.dump(), "[8000,8002]");
+    }
+
+    #[test]
+    fn test_allocate_when_exhausted() {
+        let allocator = PortAllocator::new(ReuseFast, 8000, 8000);
+
+        let lease = allocator.allocate().unwrap();
+
+        assert_eq!(allocator.allocate().is_none(), true);
+
+        drop(lease);
+
+        assert_eq!(allocator.allocate().unwrap().port(), 8000);
+    }
+
+    #[test]
+    fn test_bind_probe_skips_ports_in_use() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let port = listener.local_addr().unwrap().port();
+
+        let mut allocator = PortAllocator::new(ReuseFast, port, port);
+
+        allocator.enable_bind_probe(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        assert_eq!(allocator.allocate().is_none(), true);
+
+        assert_eq!(allocator.dump(), format!("[{}]", port));
+
+        drop(listener);
+
+        assert_eq!(allocator.allocate().unwrap().port(), port);
+    }
+}
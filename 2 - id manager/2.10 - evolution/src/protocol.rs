@@ -0,0 +1,157 @@
+use std::io;
+use std::io::{Read, Write};
+
+// Each frame is a 4 byte big endian length followed by that many bytes of UTF-8 text.
+// Ids are always sent as decimal u128 values so that clients and servers agree on the
+// wire format whatever IdType they use. A leased block comes with a token that must be sent
+// to renew or return it, which can be done from any connection.
+
+const MAX_FRAME_SIZE: u32 = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Request {
+    Lease(u128),
+    Renew(u128, u128, u128),
+    Return(u128, u128, u128),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    Leased(u128, u128, u128),
+    Unavailable,
+    Ok,
+    Error(String),
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, message: &str) -> io::Result<()> {
+    if message.len() > MAX_FRAME_SIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame too large"));
+    }
+
+    writer.write_all(&(message.len() as u32).to_be_bytes())?;
+    writer.write_all(message.as_bytes())?;
+    writer.flush()
+}
+
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut length = [0u8; 4];
+
+    reader.read_exact(&mut length)?;
+
+    let length = u32::from_be_bytes(length);
+
+    if length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+    }
+
+    let mut message = vec![0u8; length as usize];
+
+    reader.read_exact(&mut message)?;
+
+    String::from_utf8(message).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "frame is not UTF-8"))
+}
+
+impl Request {
+    pub fn encode(&self) -> String {
+        match self {
+            Request::Lease(size) => format!("LEASE {}", size),
+            Request::Renew(lower, upper, token) => format!("RENEW {} {} {}", lower, upper, token),
+            Request::Return(lower, upper, token) => format!("RETURN {} {} {}", lower, upper, token),
+        }
+    }
+
+    pub fn decode(message: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = message.split(' ').collect();
+
+        match parts.as_slice() {
+            ["LEASE", size] => Ok(Request::Lease(parse_value(size)?)),
+            ["RENEW", lower, upper, token] => Ok(Request::Renew(parse_value(lower)?, parse_value(upper)?, parse_value(token)?)),
+            ["RETURN", lower, upper, token] => Ok(Request::Return(parse_value(lower)?, parse_value(upper)?, parse_value(token)?)),
+            _ => Err(format!("invalid request: {}", message)),
+        }
+    }
+}
+
+impl Response {
+    pub fn encode(&self) -> String {
+        match self {
+            Response::Leased(lower, upper, token) => format!("LEASED {} {} {}", lower, upper, token),
+            Response::Unavailable => "UNAVAILABLE".to_string(),
+            Response::Ok => "OK".to_string(),
+            Response::Error(reason) => format!("ERROR {}", reason),
+        }
+    }
+
+    pub fn decode(message: &str) -> Result<Self, String> {
+        if let Some(reason) = message.strip_prefix("ERROR ") {
+            return Ok(Response::Error(reason.to_string()));
+        }
+
+        let parts: Vec<&str> = message.split(' ').collect();
+
+        match parts.as_slice() {
+            ["LEASED", lower, upper, token] => Ok(Response::Leased(parse_value(lower)?, parse_value(upper)?, parse_value(token)?)),
+            ["UNAVAILABLE"] => Ok(Response::Unavailable),
+            ["OK"] => Ok(Response::Ok),
+            _ => Err(format!("invalid response: {}", message)),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> Result<u128, String> {
+    value.parse::<u128>().map_err(|_| format!("invalid value: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let mut buffer = Vec::new();
+
+        write_frame(&mut buffer, "LEASE 10").unwrap();
+        write_frame(&mut buffer, "").unwrap();
+
+        assert_eq!(buffer[..4], [0, 0, 0, 8]);
+
+        let mut reader = Cursor::new(buffer);
+
+        assert_eq!(read_frame(&mut reader).unwrap(), "LEASE 10");
+        assert_eq!(read_frame(&mut reader).unwrap(), "");
+        assert_eq!(read_frame(&mut reader).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        let mut reader = Cursor::new(vec![0, 0, 4, 1]);
+
+        assert_eq!(read_frame(&mut reader).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_requests() {
+        let requests = [Request::Lease(10), Request::Renew(1, 2, 3), Request::Return(u128::MAX - 1, u128::MAX, u128::MAX)];
+
+        for request in requests {
+            assert_eq!(Request::decode(&request.encode()), Ok(request));
+        }
+
+        assert_eq!(Request::decode("LEASE"), Err("invalid request: LEASE".to_string()));
+        assert_eq!(Request::decode("LEASE -1"), Err("invalid value: -1".to_string()));
+        assert_eq!(Request::decode("RENEW 1 2"), Err("invalid request: RENEW 1 2".to_string()));
+    }
+
+    #[test]
+    fn test_responses() {
+        let responses = [Response::Leased(10, 20, 30), Response::Unavailable, Response::Ok, Response::Error("not leased".to_string())];
+
+        for response in responses {
+            assert_eq!(Response::decode(&response.encode()), Ok(response));
+        }
+
+        assert_eq!(Response::decode("LEASED 1 2"), Err("invalid response: LEASED 1 2".to_string()));
+    }
+}
@@ -0,0 +1,19 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RangeError {
+    InvalidRange,
+    IdsInUse,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeError::InvalidRange => write!(f, "new range is not valid for this operation"),
+            RangeError::IdsInUse => write!(f, "ids in the range being removed are still in use"),
+        }
+    }
+}
+
+impl Error for RangeError {}
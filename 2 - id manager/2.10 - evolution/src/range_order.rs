@@ -0,0 +1,22 @@
+use std::str::FromStr;
+
+use crate::parse_error::ParseError;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RangeOrder {
+    Ascending,
+    Descending,
+}
+
+impl FromStr for RangeOrder {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "Ascending" => Ok(RangeOrder::Ascending),
+            "Descending" => Ok(RangeOrder::Descending),
+            _ => Err(ParseError::InvalidValue(text.to_string())),
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::block_source::BlockSource;
+use crate::id_type::IdType;
+use crate::protocol::{read_frame, write_frame, Request, Response};
+use crate::reuse_policy::ReusePolicy;
+use crate::smart_id::SmartId;
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+pub struct RemoteBlockSource {
+    path: PathBuf,
+    stream: Mutex<UnixStream>,
+    blocks: Mutex<Vec<(u128, u128, u128)>>,     // lower, upper and the lease token
+    lost: Mutex<Vec<(u128, u128)>>,             // blocks whose lease couldn't be renewed
+    error: Mutex<Option<(io::ErrorKind, String)>>,
+}
+
+impl RemoteBlockSource {
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let stream = UnixStream::connect(path.as_ref())?;
+
+        Ok(RemoteBlockSource {
+            path: path.as_ref().to_path_buf(),
+            stream: Mutex::new(stream),
+            blocks: Mutex::new(Vec::new()),
+            lost: Mutex::new(Vec::new()),
+            error: Mutex::new(None),
+        })
+    }
+
+    // a block that the server no longer holds a lease for is dropped, and reported as lost,
+    // as the server may have handed it to another client
+
+    pub fn renew_all(&self) -> io::Result<()> {
+        let blocks = self.blocks.lock().unwrap().clone();
+
+        let mut result = Ok(());
+
+        for (lower, upper, token) in blocks {
+            let response = self.request(Request::Renew(lower, upper, token))?;
+
+            if response != Response::Ok {
+                self.blocks.lock().unwrap().retain(|(leased, _, _)| *leased != lower);
+
+                self.lost.lock().unwrap().push((lower, upper));
+
+                if result.is_ok() {
+                    result = Err(unexpected(&format!("failed to renew block {}-{}", lower, upper), response));
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn try_lease_block<T: IdType>(&self, size: u128) -> io::Result<Option<(T, T)>> {
+        if let Some(error) = self.error() {
+            return Err(error);
+        }
+
+        match self.request(Request::Lease(size))? {
+            Response::Leased(lower, upper, token) => {
+                if lower > upper || upper > T::MAX.to_u128() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "leased block out of range"));
+                }
+
+                self.blocks.lock().unwrap().push((lower, upper, token));
+
+                Ok(Some((T::from_u128(lower), T::from_u128(upper))))
+            }
+            Response::Unavailable => Ok(None),
+            response => Err(unexpected("failed to lease block", response)),
+        }
+    }
+
+    // the first failure to lease or renew a block, after which no more blocks are leased
+
+    pub fn error(&self) -> Option<io::Error> {
+        let error = self.error.lock().unwrap();
+
+        error.as_ref().map(|(kind, reason)| io::Error::new(*kind, reason.clone()))
+    }
+
+    fn fail(&self, error: &io::Error) {
+        self.error.lock().unwrap().get_or_insert_with(|| (error.kind(), error.to_string()));
+    }
+
+    fn take_lost<T: IdType>(&self) -> Vec<(T, T)> {
+        let lost = self.lost.lock().unwrap().drain(..).collect::<Vec<_>>();
+
+        lost.into_iter().map(|(lower, upper)| (T::from_u128(lower), T::from_u128(upper))).collect()
+    }
+
+    fn request(&self, request: Request) -> io::Result<Response> {
+        let mut stream = self.stream.lock().unwrap();
+
+        // a request is sent once more on a new connection if the old one was lost, the blocks
+        // can still be renewed and returned from it as they're identified by their tokens
+
+        let response = match exchange(&mut stream, &request) {
+            Err(error) if is_disconnected(&error) => {
+                *stream = UnixStream::connect(&self.path)?;
+
+                exchange(&mut stream, &request)
+            }
+            response => response,
+        }?;
+
+        Response::decode(&response).map_err(|reason| io::Error::new(io::ErrorKind::InvalidData, reason))
+    }
+}
+
+fn exchange(stream: &mut UnixStream, request: &Request) -> io::Result<String> {
+    write_frame(stream, &request.encode())?;
+
+    read_frame(stream)
+}
+
+fn is_disconnected(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::NotConnected | io::ErrorKind::UnexpectedEof
+    )
+}
+
+fn unexpected(context: &str, response: Response) -> io::Error {
+    match response {
+        Response::Error(reason) => io::Error::other(format!("{}: {}", context, reason)),
+        response => io::Error::new(io::ErrorKind::InvalidData, format!("{}: unexpected response {:?}", context, response)),
+    }
+}
+
+impl<T: IdType> BlockSource<T> for RemoteBlockSource {
+    fn lease_block(&self, size: u128) -> Option<(T, T)> {
+        // the child manager can only be told that there are no more ids, the error is kept
+        // for RemoteIdManager::try_allocate_id() to report
+
+        self.try_lease_block(size).unwrap_or_else(|error| {
+            self.fail(&error);
+
+            None
+        })
+    }
+
+    fn return_block(&self, lower: T, upper: T) {
+        let block = (lower.to_u128(), upper.to_u128());
+
+        let token = {
+            let mut blocks = self.blocks.lock().unwrap();
+
+            let index = blocks.iter().position(|(lower, upper, _)| (*lower, *upper) == block);
+
+            index.map(|index| blocks.remove(index).2)
+        };
+
+        // this is called when dropping, if the server can't be told then it will reclaim
+        // the block when the lease expires
+
+        if let Some(token) = token {
+            let _ = self.request(Request::Return(block.0, block.1, token));
+        }
+    }
+}
+
+// Allocates ids from blocks leased, on demand, from a LeaseServer. The leases are renewed
+// in the background for as long as any ids or the manager itself are alive. Once a lease
+// can't be renewed no more ids are allocated, as the server may have handed the blocks to
+// another client.
+
+pub struct RemoteIdManager<T: IdType> {
+    source: Arc<RemoteBlockSource>,
+    reuse_policy: ReusePolicy,
+    block_size: u128,
+    manager: Mutex<Option<ThreadSafeIdManager<T>>>,
+}
+
+impl<T: IdType> RemoteIdManager<T> {
+    pub fn connect<P: AsRef<Path>>(path: P, reuse_policy: ReusePolicy, block_size: u128, renew_every: Duration) -> io::Result<Self> {
+        let source = Arc::new(RemoteBlockSource::connect(path)?);
+
+        let renewing = Arc::downgrade(&source);
+
+        thread::spawn(move || loop {
+            thread::sleep(renew_every);
+
+            match renewing.upgrade() {
+                Some(source) => {
+                    if let Err(error) = source.renew_all() {
+                        source.fail(&error);
+
+                        break;
+                    }
+                }
+                None => break,
+            }
+        });
+
+        Ok(RemoteIdManager { source, reuse_policy, block_size, manager: Mutex::new(None) })
+    }
+
+    pub fn allocate_id(&self) -> SmartId<T> {
+        match self.try_allocate_id() {
+            Ok(Some(id)) => id,
+            Ok(None) => panic!("No Ids available"),
+            Err(error) => panic!("failed to allocate id: {}", error),
+        }
+    }
+
+    // returns None once the server has no more ids, or the error that stopped the leases
+    // being renewed or another block being leased
+
+    pub fn try_allocate_id(&self) -> io::Result<Option<SmartId<T>>> {
+        let mut manager = self.manager.lock().unwrap();
+
+        if let Some(manager) = manager.as_ref() {
+            for (lower, upper) in self.source.take_lost::<T>() {
+                manager.revoke_block(lower, upper);
+            }
+        }
+
+        if let Some(error) = self.source.error() {
+            return Err(error);
+        }
+
+        if manager.is_none() {
+            let source: Arc<dyn BlockSource<T>> = self.source.clone();
+
+            *manager = ThreadSafeIdManager::lease_from(source, self.reuse_policy, self.block_size);
+        }
+
+        match manager.as_ref().and_then(|manager| manager.try_allocate_id()) {
+            Some(id) => Ok(Some(id)),
+            None => self.source.error().map_or(Ok(None), Err),
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        match self.manager.lock().unwrap().as_ref() {
+            Some(manager) => manager.dump(),
+            None => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::Instant;
+
+    use crate::lease_server::LeaseServer;
+    use crate::reuse_policy::ReusePolicy::ReuseFast;
+
+    use super::*;
+
+    fn bind(name: &str) -> (Arc<LeaseServer<u16>>, UnixListener, PathBuf) {
+        let path = std::env::temp_dir().join(format!("idmanager-{}-{}.sock", process::id(), name));
+
+        let _ = fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let manager = ThreadSafeIdManager::<u16>::new_limited_range(ReuseFast, 100, 199);
+
+        (Arc::new(LeaseServer::new(manager, Duration::from_secs(60))), listener, path)
+    }
+
+    fn start_server(name: &str) -> (Arc<LeaseServer<u16>>, PathBuf) {
+        let (server, listener, path) = bind(name);
+
+        let serving = server.clone();
+
+        thread::spawn(move || serving.serve(listener));
+
+        (server, path)
+    }
+
+    #[test]
+    fn test_remote_id_manager() {
+        let (server, path) = start_server("remote");
+
+        let remote = RemoteIdManager::<u16>::connect(&path, ReuseFast, 10, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(remote.dump(), "");
+
+        assert_eq!(server.manager().dump(), "[100,199]");
+
+        let mut ids = Vec::new();
+
+        for _ in 0..12 {
+            ids.push(remote.allocate_id());
+        }
+
+        assert_eq!(ids[0].value(), &100);
+        assert_eq!(ids[11].value(), &111);
+
+        assert_eq!(server.manager().dump(), "[120,199]");
+
+        let other = RemoteIdManager::<u16>::connect(&path, ReuseFast, 10, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(other.allocate_id().value(), &120);
+
+        drop(remote);
+
+        assert_eq!(server.manager().dump(), "[130,199]");
+
+        ids.clear();
+
+        assert_eq!(server.manager().dump(), "[100,119], [130,199]");
+
+        drop(other);
+
+        assert_eq!(server.manager().dump(), "[100,199]");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_lost_lease_stops_allocation() {
+        let (server, path) = start_server("lost");
+
+        let remote = RemoteIdManager::<u16>::connect(&path, ReuseFast, 10, Duration::from_secs(60)).unwrap();
+
+        let id = remote.allocate_id();
+
+        assert_eq!(id.value(), &100);
+
+        // the server reclaims the block as if the client had stopped renewing it
+
+        assert_eq!(server.reap_expired(Instant::now() + Duration::from_secs(60)), 1);
+
+        let error = remote.source.renew_all().unwrap_err();
+
+        assert_eq!(error.to_string(), "failed to renew block 100-109: block 100-109 is not leased");
+
+        remote.source.fail(&error);
+
+        assert_eq!(remote.try_allocate_id().err().map(|error| error.to_string()), Some(error.to_string()));
+
+        assert_eq!(remote.dump(), "");
+
+        // the block may now belong to someone else so it isn't freed into or returned from
+        // the client
+
+        let other = RemoteIdManager::<u16>::connect(&path, ReuseFast, 10, Duration::from_secs(60)).unwrap();
+
+        assert_eq!(other.allocate_id().value(), &100);
+
+        drop(id);
+        drop(remote);
+
+        assert_eq!(server.manager().dump(), "[110,199]");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let (server, listener, path) = bind("reconnect");
+
+        let serving = server.clone();
+
+        // the first connection is closed after one request
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let request = Request::decode(&read_frame(&mut stream).unwrap()).unwrap();
+
+            write_frame(&mut stream, &serving.handle_request(request, Instant::now()).encode()).unwrap();
+
+            drop(stream);
+
+            serving.serve(listener)
+        });
+
+        let remote = RemoteIdManager::<u16>::connect(&path, ReuseFast, 10, Duration::from_secs(60)).unwrap();
+
+        let id = remote.allocate_id();
+
+        assert_eq!(server.manager().dump(), "[110,199]");
+
+        assert_eq!(remote.source.renew_all().is_ok(), true);
+
+        drop(id);
+        drop(remote);
+
+        assert_eq!(server.manager().dump(), "[100,199]");
+
+        let _ = fs::remove_file(&path);
+    }
+}
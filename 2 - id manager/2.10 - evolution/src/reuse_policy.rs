@@ -1,5 +1,50 @@
-#[derive(PartialEq)]
+use std::str::FromStr;
+
+use crate::parse_error::ParseError;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReusePolicy {
     ReuseFast,
     ReuseSlow,
+    // allocates a free id chosen at random, the same seed always gives the same sequence
+    ReuseRandom(u64),
+}
+
+impl FromStr for ReusePolicy {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "ReuseFast" => Ok(ReusePolicy::ReuseFast),
+            "ReuseSlow" => Ok(ReusePolicy::ReuseSlow),
+            _ => {
+                // as formatted by {:?}, e.g. ReuseRandom(42)
+
+                let seed = text
+                    .strip_prefix("ReuseRandom(")
+                    .and_then(|seed| seed.strip_suffix(')'))
+                    .and_then(|seed| seed.parse::<u64>().ok())
+                    .ok_or_else(|| ParseError::InvalidValue(text.to_string()))?;
+
+                Ok(ReusePolicy::ReuseRandom(seed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("ReuseFast".parse(), Ok(ReusePolicy::ReuseFast));
+        assert_eq!("ReuseSlow".parse(), Ok(ReusePolicy::ReuseSlow));
+        assert_eq!("ReuseRandom(42)".parse(), Ok(ReusePolicy::ReuseRandom(42)));
+        assert_eq!(format!("{:?}", ReusePolicy::ReuseRandom(42)).parse(), Ok(ReusePolicy::ReuseRandom(42)));
+
+        assert_eq!("ReuseRandom(x)".parse::<ReusePolicy>(), Err(ParseError::InvalidValue("ReuseRandom(x)".to_string())));
+        assert_eq!("ReuseRandom".parse::<ReusePolicy>(), Err(ParseError::InvalidValue("ReuseRandom".to_string())));
+    }
 }
@@ -1,31 +1,61 @@
 use std::fmt;
-use std::sync::{Arc, Mutex};
 
-use crate::id_manager::IdManager;
+use crate::free_set::FreeSet;
 use crate::id_type::IdType;
+use crate::intervals::Intervals;
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
 
-pub struct SmartId<T: IdType> {
-    manager: Arc<Mutex<IdManager<T>>>,
+// An allocated id that is freed when dropped. A reserved id, from reserve_id(), must be
+// committed to keep it, otherwise it is aborted when dropped.
+
+pub struct SmartId<T: IdType, S: FreeSet<T> = Intervals<T>> {
+    manager: ThreadSafeIdManager<T, S>,
     id: T,
     we_own_id: bool,
+    committed: bool,
 }
 
-impl<T: IdType> SmartId<T> {
-    pub fn new(manager: Arc<Mutex<IdManager<T>>>) -> Self {
-        let mut locked = manager.lock().unwrap();
+impl<T: IdType, S: FreeSet<T>> SmartId<T, S> {
+    pub fn new(manager: ThreadSafeIdManager<T, S>) -> Self {
+        let id = manager.allocate();
 
-        if !locked.can_allocate()
-        {
-            panic!("No Ids available")
+        SmartId { manager, id, we_own_id: true, committed: true }
+    }
+
+    pub(crate) fn from_id(manager: ThreadSafeIdManager<T, S>, id: T) -> Self {
+        SmartId { manager, id, we_own_id: true, committed: true }
+    }
+
+    pub(crate) fn reserved(manager: ThreadSafeIdManager<T, S>, id: T) -> Self {
+        SmartId { manager, id, we_own_id: true, committed: false }
+    }
+
+    pub fn is_committed(&self) -> bool {
+        self.committed
+    }
+
+    pub fn commit(&mut self) {
+        if self.committed {
+            panic!("id has already been committed");
         }
 
-        let id = locked.allocate();
+        self.manager.commit(self.id);
 
-        SmartId { manager : manager.clone(), id, we_own_id: true }
+        self.committed = true;
+    }
+
+    pub fn abort(self) {
+        if self.committed {
+            panic!("id has already been committed");
+        }
+
+        // dropping an uncommitted id aborts it
     }
 
     pub fn release(&mut self) -> T {
-        let _locked = self.manager.lock().unwrap();
+        if !self.committed {
+            panic!("a reserved id must be committed before it is released");
+        }
 
         self.we_own_id = false;
 
@@ -37,34 +67,37 @@ impl<T: IdType> SmartId<T> {
     }
 }
 
-impl<T: IdType> fmt::Display for SmartId< T> {
+impl<T: IdType, S: FreeSet<T>> fmt::Display for SmartId<T, S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "[{}]", self.id)
     }
 }
 
-impl<T: IdType> Drop for SmartId<T> {
+impl<T: IdType, S: FreeSet<T>> Drop for SmartId<T, S> {
     fn drop(&mut self) {
-        let mut locked = self.manager.lock().unwrap();
-
-        if self.we_own_id
+        if !self.committed {
+            self.manager.abort(self.id);
+        } else if self.we_own_id
         {
-            locked.free(self.id);
+            self.manager.free(self.id);
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::observer::IdManagerEvent;
     use crate::reuse_policy::ReusePolicy::ReuseSlow;
 
     use super::*;
 
     #[test]
     fn test_create_one_smart_id() {
-        let manager = Arc::new(Mutex::new(IdManager::<u8>::new(ReuseSlow)));
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
 
-        assert_eq!(manager.lock().unwrap().dump(), "[0,255]");
+        assert_eq!(manager.dump(), "[0,255]");
 
         {
             let id1 = SmartId::<u8>::new(manager.clone());
@@ -73,17 +106,17 @@ mod tests {
 
             assert_eq!(id1.value(), &expected_id);
 
-            assert_eq!(manager.lock().unwrap().dump(), "[1,255]");
+            assert_eq!(manager.dump(), "[1,255]");
         }
 
-        assert_eq!(manager.lock().unwrap().dump(), "[0,255]");
+        assert_eq!(manager.dump(), "[0,255]");
     }
 
     #[test]
     fn test_create_multiple_smart_ids() {
-        let manager = Arc::new(Mutex::new(IdManager::<u8>::new(ReuseSlow)));
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
 
-        assert_eq!(manager.lock().unwrap().dump(), "[0,255]");
+        assert_eq!(manager.dump(), "[0,255]");
 
         {
             let id1 = SmartId::<u8>::new(manager.clone());
@@ -92,7 +125,7 @@ mod tests {
 
             assert_eq!(id1.value(), &expected_id1);
 
-            assert_eq!(manager.lock().unwrap().dump(), "[1,255]");
+            assert_eq!(manager.dump(), "[1,255]");
 
             {
                 let mut id2 = SmartId::new(manager.clone());
@@ -101,7 +134,7 @@ mod tests {
 
                 assert_eq!(id2.value(), &expected_id2);
 
-                assert_eq!(manager.lock().unwrap().dump(), "[2,255]");
+                assert_eq!(manager.dump(), "[2,255]");
 
                 id2.release();
 
@@ -112,32 +145,116 @@ mod tests {
 
                     assert_eq!(id3.value(), &expected_id);
 
-                    assert_eq!(manager.lock().unwrap().dump(), "[3,255]");
+                    assert_eq!(manager.dump(), "[3,255]");
                 }
             }
 
-            assert_eq!(manager.lock().unwrap().dump(), "[2,255]");
+            assert_eq!(manager.dump(), "[2,255]");
         }
 
-        assert_eq!(manager.lock().unwrap().dump(), "[0], [2,255]");
+        assert_eq!(manager.dump(), "[0], [2,255]");
     }
 
     #[test]
     fn test_release() {
-        let manager = Arc::new(Mutex::new(IdManager::<u8>::new(ReuseSlow)));
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
 
-        assert_eq!(manager.lock().unwrap().dump(), "[0,255]");
+        assert_eq!(manager.dump(), "[0,255]");
 
         {
             let mut id1 = SmartId::<u8>::new(manager.clone());
 
-            assert_eq!(manager.lock().unwrap().dump(), "[1,255]");
+            assert_eq!(manager.dump(), "[1,255]");
 
             id1.release();
 
-            assert_eq!(manager.lock().unwrap().dump(), "[1,255]");
+            assert_eq!(manager.dump(), "[1,255]");
+        }
+
+        assert_eq!(manager.dump(), "[1,255]");
+    }
+
+    #[test]
+    fn test_reserve_and_commit() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        {
+            let mut id = manager.reserve_id();
+
+            assert_eq!(id.value(), &10);
+            assert_eq!(id.is_committed(), false);
+            assert_eq!(manager.stats().reserved, 1);
+
+            id.commit();
+
+            assert_eq!(id.is_committed(), true);
+            assert_eq!(manager.stats().reserved, 0);
+            assert_eq!(manager.stats().allocated, 1);
         }
 
-        assert_eq!(manager.lock().unwrap().dump(), "[1,255]");
+        assert_eq!(manager.dump(), "[10,20]");
+
+        // ReuseSlow has moved on from the committed id
+
+        assert_eq!(manager.allocate_id().value(), &11);
+    }
+
+    #[test]
+    fn test_drop_aborts_reservation() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let events = events.clone();
+
+            manager.add_observer(move |event: &IdManagerEvent<u8>| events.lock().unwrap().push(*event));
+        }
+
+        let reserved = manager.reserve_id();
+
+        drop(reserved);
+
+        manager.reserve_id().abort();
+
+        assert_eq!(manager.dump(), "[10,20]");
+
+        let mut id = manager.reserve_id();
+
+        assert_eq!(id.value(), &10);
+
+        id.commit();
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            IdManagerEvent::Reserved(10),
+            IdManagerEvent::Aborted(10),
+            IdManagerEvent::Reserved(10),
+            IdManagerEvent::Aborted(10),
+            IdManagerEvent::Reserved(10),
+            IdManagerEvent::Allocated(10)]);
+    }
+
+    #[test]
+    fn test_try_reserve_id() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 10);
+
+        let id = manager.try_reserve_id().unwrap();
+
+        assert_eq!(manager.try_reserve_id().is_none(), true);
+        assert_eq!(manager.try_allocate_id().is_none(), true);
+
+        drop(id);
+
+        assert_eq!(manager.try_allocate_id().is_some(), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "a reserved id must be committed before it is released")]
+    fn test_release_reserved() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        let mut id = manager.reserve_id();
+
+        id.release();
     }
 }
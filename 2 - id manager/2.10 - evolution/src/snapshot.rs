@@ -0,0 +1,185 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::id_type::IdType;
+use crate::intervals::Intervals;
+use crate::parse_error::ParseError;
+use crate::range_order::RangeOrder;
+use crate::reuse_policy::ReusePolicy;
+
+// The state needed to recreate an IdManager, formatted as a single line, for example:
+//
+//   policy=ReuseSlow; order=Ascending; ranges=[10,210]; next=13; free=[10], [13,200]
+//
+// Reserved ids aren't recorded separately so they are restored as allocated, and aborted ids
+// are restored as ordinary free ids.
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IdManagerSnapshot<T: IdType> {
+    pub reuse_policy: ReusePolicy,
+    pub range_order: RangeOrder,
+    pub ranges: Intervals<T>,
+    pub next_to_allocate: T,
+    pub free_ids: Intervals<T>,
+}
+
+impl<T: IdType> IdManagerSnapshot<T> {
+    pub fn validate(&self) -> Result<(), ParseError> {
+        if self.ranges.is_empty() {
+            return Err(ParseError::InvalidFormat("ranges must not be empty".to_string()));
+        }
+
+        if !self.ranges.contains_interval(self.next_to_allocate, self.next_to_allocate) {
+            return Err(ParseError::OutOfRange);
+        }
+
+        for interval in self.free_ids.iter() {
+            if !self.ranges.contains_interval(interval.lower(), interval.upper()) {
+                return Err(ParseError::OutOfRange);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: IdType> fmt::Display for IdManagerSnapshot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "policy={:?}; order={:?}; ranges={}; next={}; free={}",
+               self.reuse_policy, self.range_order, self.ranges, self.next_to_allocate, self.free_ids)
+    }
+}
+
+impl<T: IdType> FromStr for IdManagerSnapshot<T> {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut reuse_policy = None;
+        let mut range_order = None;
+        let mut ranges = None;
+        let mut next_to_allocate = None;
+        let mut free_ids = None;
+
+        for field in text.split(';') {
+            let (name, value) = field
+                .split_once('=')
+                .ok_or_else(|| ParseError::InvalidFormat(field.trim().to_string()))?;
+
+            let value = value.trim();
+
+            match name.trim() {
+                "policy" => reuse_policy = Some(value.parse::<ReusePolicy>()?),
+                "order" => range_order = Some(value.parse::<RangeOrder>()?),
+                "ranges" => ranges = Some(value.parse::<Intervals<T>>()?),
+                "next" => next_to_allocate = Some(value.parse::<T>().map_err(|_| ParseError::InvalidValue(value.to_string()))?),
+                "free" => free_ids = Some(value.parse::<Intervals<T>>()?),
+                name => return Err(ParseError::InvalidFormat(name.to_string())),
+            }
+        }
+
+        let missing = |name: &str| ParseError::InvalidFormat(format!("missing {}", name));
+
+        let snapshot = IdManagerSnapshot {
+            reuse_policy: reuse_policy.ok_or_else(|| missing("policy"))?,
+            range_order: range_order.ok_or_else(|| missing("order"))?,
+            ranges: ranges.ok_or_else(|| missing("ranges"))?,
+            next_to_allocate: next_to_allocate.ok_or_else(|| missing("next"))?,
+            free_ids: free_ids.ok_or_else(|| missing("free"))?,
+        };
+
+        snapshot.validate()?;
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedSnapshot<T: IdType> {
+    reuse_policy: ReusePolicy,
+    range_order: RangeOrder,
+    ranges: Intervals<T>,
+    next_to_allocate: T,
+    free_ids: Intervals<T>,
+}
+
+// validated in the same way as from_str()
+
+#[cfg(feature = "serde")]
+impl<'de, T: IdType + serde::Deserialize<'de>> serde::Deserialize<'de> for IdManagerSnapshot<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let unchecked = UncheckedSnapshot::<T>::deserialize(deserializer)?;
+
+        let snapshot = IdManagerSnapshot {
+            reuse_policy: unchecked.reuse_policy,
+            range_order: unchecked.range_order,
+            ranges: unchecked.ranges,
+            next_to_allocate: unchecked.next_to_allocate,
+            free_ids: unchecked.free_ids,
+        };
+
+        snapshot.validate().map_err(serde::de::Error::custom)?;
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = "policy=ReuseSlow; order=Ascending; ranges=[10,210]; next=13; free=[10], [13,200]";
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = SNAPSHOT.parse::<IdManagerSnapshot<u8>>().unwrap();
+
+        assert_eq!(snapshot.reuse_policy, ReusePolicy::ReuseSlow);
+        assert_eq!(snapshot.range_order, RangeOrder::Ascending);
+        assert_eq!(snapshot.next_to_allocate, 13);
+        assert_eq!(snapshot.free_ids.dump(), "[10], [13,200]");
+
+        assert_eq!(snapshot.to_string(), SNAPSHOT);
+    }
+
+    #[test]
+    fn test_empty_free_ids() {
+        let snapshot = "policy=ReuseFast; order=Descending; ranges=[1,2], [5,6]; next=5; free=".parse::<IdManagerSnapshot<u8>>().unwrap();
+
+        assert_eq!(snapshot.free_ids.is_empty(), true);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let parse = |text: &str| text.parse::<IdManagerSnapshot<u8>>().err();
+
+        assert_eq!(parse("policy=ReuseSlow"), Some(ParseError::InvalidFormat("missing order".to_string())));
+        assert_eq!(parse("policy=ReuseSometimes"), Some(ParseError::InvalidValue("ReuseSometimes".to_string())));
+        assert_eq!(parse("colour=red"), Some(ParseError::InvalidFormat("colour".to_string())));
+        assert_eq!(parse("policy"), Some(ParseError::InvalidFormat("policy".to_string())));
+        assert_eq!(parse("policy=ReuseSlow; order=Ascending; ranges=; next=13; free="), Some(ParseError::InvalidFormat("ranges must not be empty".to_string())));
+        assert_eq!(parse("policy=ReuseSlow; order=Ascending; ranges=[10,210]; next=9; free="), Some(ParseError::OutOfRange));
+        assert_eq!(parse("policy=ReuseSlow; order=Ascending; ranges=[10,210]; next=10; free=[200,211]"), Some(ParseError::OutOfRange));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let snapshot = SNAPSHOT.parse::<IdManagerSnapshot<u8>>().unwrap();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+
+        assert_eq!(json, r#"{"reuse_policy":"ReuseSlow","range_order":"Ascending","ranges":[[10,210]],"next_to_allocate":13,"free_ids":[[10,10],[13,200]]}"#);
+
+        assert_eq!(serde_json::from_str::<IdManagerSnapshot<u8>>(&json).unwrap().to_string(), SNAPSHOT);
+
+        let invalid = json.replace("[13,200]", "[13,211]");
+
+        assert_eq!(serde_json::from_str::<IdManagerSnapshot<u8>>(&invalid).err().unwrap().to_string(), "value is out of range");
+
+        let invalid = json.replace("ReuseSlow", "ReuseSometimes");
+
+        assert_eq!(serde_json::from_str::<IdManagerSnapshot<u8>>(&invalid).is_err(), true);
+    }
+}
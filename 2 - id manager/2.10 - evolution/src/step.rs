@@ -0,0 +1,276 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// A type whose values form a sequence from MIN to MAX with no gaps, so that runs of
+// consecutive values can be held as intervals. Every IdType is a Step, as are char, Ipv4Addr,
+// Ipv6Addr and, with the chrono feature, chrono::NaiveDate.
+
+pub trait Step: Ord + fmt::Display + Copy + Send + Sync + 'static {
+    const MIN: Self;
+    const MAX: Self;
+
+    // None when self is MAX
+    fn successor(self) -> Option<Self>;
+
+    // None when self is MIN
+    fn predecessor(self) -> Option<Self>;
+
+    // the number of steps from lower up to upper, saturating at u128::MAX
+    fn steps_between(lower: Self, upper: Self) -> u128;
+}
+
+// for values that are known not to be at the end of the sequence
+
+pub(crate) fn next<T: Step>(value: T) -> T {
+    value.successor().expect("no value after MAX")
+}
+
+pub(crate) fn prev<T: Step>(value: T) -> T {
+    value.predecessor().expect("no value before MIN")
+}
+
+macro_rules! step_trait_impl {
+    ($name:ident for $($t:ty)*) => ($(
+    impl $name for $t {
+        const MIN : $t = <$t>::MIN;
+        const MAX : $t = <$t>::MAX;
+
+        fn successor(self) -> Option<Self> {
+            self.checked_add(1)
+        }
+
+        fn predecessor(self) -> Option<Self> {
+            self.checked_sub(1)
+        }
+
+        fn steps_between(lower: Self, upper: Self) -> u128 {
+            (upper - lower) as u128
+        }
+    }
+    )*)
+}
+
+step_trait_impl!(Step for u8 u16 u32 u64 u128 usize);
+
+// skips the surrogates, which aren't valid chars
+
+const BEFORE_SURROGATES: u32 = 0xD7FF;
+const AFTER_SURROGATES: u32 = 0xE000;
+
+impl Step for char {
+    const MIN: char = '\0';
+    const MAX: char = char::MAX;
+
+    fn successor(self) -> Option<Self> {
+        match self as u32 {
+            BEFORE_SURROGATES => char::from_u32(AFTER_SURROGATES),
+            value => char::from_u32(value.checked_add(1)?),
+        }
+    }
+
+    fn predecessor(self) -> Option<Self> {
+        match self as u32 {
+            AFTER_SURROGATES => char::from_u32(BEFORE_SURROGATES),
+            value => char::from_u32(value.checked_sub(1)?),
+        }
+    }
+
+    fn steps_between(lower: Self, upper: Self) -> u128 {
+        let steps = (upper as u32 - lower as u32) as u128;
+
+        if (lower as u32) <= BEFORE_SURROGATES && (upper as u32) >= AFTER_SURROGATES {
+            steps - (AFTER_SURROGATES - BEFORE_SURROGATES - 1) as u128
+        } else {
+            steps
+        }
+    }
+}
+
+impl Step for Ipv4Addr {
+    const MIN: Ipv4Addr = Ipv4Addr::UNSPECIFIED;
+    const MAX: Ipv4Addr = Ipv4Addr::BROADCAST;
+
+    fn successor(self) -> Option<Self> {
+        u32::from(self).successor().map(Ipv4Addr::from)
+    }
+
+    fn predecessor(self) -> Option<Self> {
+        u32::from(self).predecessor().map(Ipv4Addr::from)
+    }
+
+    fn steps_between(lower: Self, upper: Self) -> u128 {
+        u32::steps_between(lower.into(), upper.into())
+    }
+}
+
+impl Step for Ipv6Addr {
+    const MIN: Ipv6Addr = Ipv6Addr::UNSPECIFIED;
+    const MAX: Ipv6Addr = Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff);
+
+    fn successor(self) -> Option<Self> {
+        u128::from(self).successor().map(Ipv6Addr::from)
+    }
+
+    fn predecessor(self) -> Option<Self> {
+        u128::from(self).predecessor().map(Ipv6Addr::from)
+    }
+
+    fn steps_between(lower: Self, upper: Self) -> u128 {
+        u128::steps_between(lower.into(), upper.into())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Step for chrono::NaiveDate {
+    const MIN: chrono::NaiveDate = chrono::NaiveDate::MIN;
+    const MAX: chrono::NaiveDate = chrono::NaiveDate::MAX;
+
+    fn successor(self) -> Option<Self> {
+        self.succ_opt()
+    }
+
+    fn predecessor(self) -> Option<Self> {
+        self.pred_opt()
+    }
+
+    fn steps_between(lower: Self, upper: Self) -> u128 {
+        (upper - lower).num_days() as u128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intervals::Intervals;
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+    enum Weekday {
+        Monday,
+        Tuesday,
+        Wednesday,
+        Thursday,
+        Friday,
+        Saturday,
+        Sunday,
+    }
+
+    const WEEKDAYS: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    impl fmt::Display for Weekday {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl Step for Weekday {
+        const MIN: Weekday = Weekday::Monday;
+        const MAX: Weekday = Weekday::Sunday;
+
+        fn successor(self) -> Option<Self> {
+            WEEKDAYS.get(self as usize + 1).copied()
+        }
+
+        fn predecessor(self) -> Option<Self> {
+            WEEKDAYS.get((self as usize).checked_sub(1)?).copied()
+        }
+
+        fn steps_between(lower: Self, upper: Self) -> u128 {
+            (upper as usize - lower as usize) as u128
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(u8::MAX.successor(), None);
+        assert_eq!(u8::MIN.predecessor(), None);
+        assert_eq!(10u8.successor(), Some(11));
+        assert_eq!(10u8.predecessor(), Some(9));
+        assert_eq!(u128::steps_between(u128::MIN, u128::MAX), u128::MAX);
+    }
+
+    #[test]
+    fn test_char() {
+        assert_eq!('a'.successor(), Some('b'));
+        assert_eq!('\u{D7FF}'.successor(), Some('\u{E000}'));
+        assert_eq!('\u{E000}'.predecessor(), Some('\u{D7FF}'));
+        assert_eq!(char::MAX.successor(), None);
+        assert_eq!('\0'.predecessor(), None);
+
+        assert_eq!(char::steps_between('a', 'z'), 25);
+        assert_eq!(char::steps_between('\u{D7FF}', '\u{E000}'), 1);
+
+        let mut intervals = Intervals::<char>::new();
+
+        assert_eq!(intervals.insert_interval('a', 'f'), true);
+        assert_eq!(intervals.insert_interval('g', 'z'), true);
+        assert_eq!(intervals.insert_value('\u{D7FF}'), true);
+        assert_eq!(intervals.insert_value('\u{E000}'), true);
+
+        assert_eq!(intervals.dump(), "[a,z], [\u{D7FF},\u{E000}]");
+        assert_eq!(intervals.value_count(), 28);
+    }
+
+    #[test]
+    fn test_ip_addresses() {
+        let mut pool = Intervals::<Ipv4Addr>::new();
+
+        pool.insert_interval(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(10, 0, 0, 255));
+
+        assert_eq!(pool.remove_first_value(), Ipv4Addr::new(10, 0, 0, 0));
+        assert_eq!(pool.remove_value(Ipv4Addr::new(10, 0, 0, 255)), true);
+        assert_eq!(pool.insert_value(Ipv4Addr::new(10, 0, 1, 0)), true);
+
+        assert_eq!(pool.dump(), "[10.0.0.1,10.0.0.254], [10.0.1.0]");
+        assert_eq!(pool.value_count(), 255);
+
+        assert_eq!(Ipv4Addr::BROADCAST.successor(), None);
+
+        let mut pool = Intervals::<Ipv6Addr>::new();
+
+        pool.insert_interval("fd00::".parse().unwrap(), "fd00::ffff".parse().unwrap());
+        pool.remove_value("fd00::ff".parse().unwrap());
+
+        assert_eq!(pool.dump(), "[fd00::,fd00::fe], [fd00::100,fd00::ffff]");
+        assert_eq!(pool.value_count(), 65535);
+        assert_eq!(Ipv6Addr::steps_between(Ipv6Addr::MIN, Ipv6Addr::MAX), u128::MAX);
+    }
+
+    #[test]
+    fn test_enum() {
+        let mut working = Intervals::<Weekday>::new();
+
+        working.insert_interval(Weekday::Monday, Weekday::Wednesday);
+        working.insert_interval(Weekday::Thursday, Weekday::Friday);
+        working.insert_value(Weekday::Sunday);
+
+        assert_eq!(working.dump(), "[Monday,Friday], [Sunday]");
+        assert_eq!(working.value_count(), 6);
+        assert_eq!(working.contains_value(Weekday::Saturday), false);
+
+        let days: Vec<Weekday> = working.iter().next().unwrap().iter().collect();
+
+        assert_eq!(days, WEEKDAYS[..5].to_vec());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_dates() {
+        use chrono::NaiveDate;
+
+        let mut booked = Intervals::<NaiveDate>::new();
+
+        booked.insert_interval(NaiveDate::from_ymd_opt(2024, 2, 27).unwrap(), NaiveDate::from_ymd_opt(2024, 3, 2).unwrap());
+        booked.insert_value(NaiveDate::from_ymd_opt(2024, 3, 3).unwrap());
+
+        assert_eq!(booked.dump(), "[2024-02-27,2024-03-03]");
+        assert_eq!(booked.value_count(), 6);
+    }
+}
@@ -0,0 +1,8 @@
+// Building with RUSTFLAGS="--cfg loom" swaps in loom's versions of the primitives used by
+// ThreadSafeIdManager so that the tests in loom_tests.rs can explore every interleaving.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{Mutex, MutexGuard};
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Mutex, MutexGuard};
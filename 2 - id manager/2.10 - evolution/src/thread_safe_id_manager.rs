@@ -1,399 +1,1537 @@
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::block_source::BlockSource;
+use crate::capacity_threshold::{CapacityAlert, CapacityThreshold, Watermark};
+use crate::free_set::FreeSet;
 use crate::id_manager::IdManager;
+use crate::id_manager_stats::IdManagerStats;
 use crate::id_type::IdType;
+use crate::intervals::Intervals;
+use crate::leak_tracker::{LeakTracker, LiveId};
+use crate::observer::{IdManagerEvent, IdManagerObserver, ObserverId};
+use crate::parse_error::ParseError;
+use crate::range_error::RangeError;
+use crate::range_order::RangeOrder;
 use crate::reuse_policy::ReusePolicy;
 use crate::smart_id::SmartId;
+use crate::snapshot::IdManagerSnapshot;
+use crate::sync::{Mutex, MutexGuard};
+use crate::trace::Trace;
+use crate::ttl_lease::{self, LeaseReaper, LeaseTable, TtlLease};
+
+struct Observers<T: IdType> {
+    next_id: u64,
+    observers: Vec<(ObserverId, Arc<dyn IdManagerObserver<T>>)>,
+}
+
+type AlertCallback = Arc<dyn Fn(&CapacityAlert) + Send + Sync>;
+
+// Alerts are queued whilst the manager is locked, so they are in the order that the capacity
+// changed, and then delivered by one thread at a time so that a Recovered is never delivered
+// before the Low that it follows.
+
+struct AlertQueue {
+    pending: VecDeque<(AlertCallback, CapacityAlert)>,
+    delivering: bool,
+}
+
+// A child manager holds the blocks that it has leased from its parent, these are returned to
+// the parent when the last clone of the child, including those held by SmartIds, is dropped.
+
+struct ParentLease<T: IdType> {
+    parent: Arc<dyn BlockSource<T>>,
+    block_size: u128,
+    blocks: Mutex<Vec<(T, T)>>,
+    revoked: Mutex<Intervals<T>>,       // blocks that were lost, their ids are never freed
+}
+
+impl<T: IdType> ParentLease<T> {
+    fn lease_block(&self) -> Option<(T, T)> {
+        let block = self.parent.lease_block(self.block_size)?;
+
+        self.blocks.lock().unwrap().push(block);
+
+        Some(block)
+    }
+
+    fn is_revoked(&self, id: T) -> bool {
+        self.revoked.lock().unwrap().contains_interval(id, id)
+    }
+}
+
+impl<T: IdType> Drop for ParentLease<T> {
+    fn drop(&mut self) {
+        for (lower, upper) in self.blocks.lock().unwrap().drain(..) {
+            self.parent.return_block(lower, upper);
+        }
+    }
+}
 
 #[derive(Clone)]
-pub struct ThreadSafeIdManager<T: IdType> {
-    manager: Arc<Mutex<IdManager<T>>>,
+pub struct ThreadSafeIdManager<T: IdType, S: FreeSet<T> = Intervals<T>> {
+    manager: Arc<Mutex<IdManager<T, S>>>,
+    observers: Arc<Mutex<Observers<T>>>,
+    watermarks: Arc<Mutex<Vec<(Watermark, AlertCallback)>>>,
+    alerts: Arc<Mutex<AlertQueue>>,
+    leak_tracker: Arc<Mutex<Option<LeakTracker<T>>>>,
+    leases: Arc<Mutex<LeaseTable<T>>>,
+    parent: Option<Arc<ParentLease<T>>>,
 }
 
-impl<T: IdType> ThreadSafeIdManager<T> {
+impl<T: IdType, S: FreeSet<T>> ThreadSafeIdManager<T, S> {
     pub fn new(reuse_policy: ReusePolicy) -> Self {
-        let manager = Arc::new(Mutex::new(IdManager::<T>::new(reuse_policy)));
+        Self::from_manager(IdManager::<T, S>::new(reuse_policy))
+    }
+
+    pub fn new_limited_range(reuse_policy: ReusePolicy, min_id: T, max_id: T) -> Self {
+        Self::from_manager(IdManager::<T, S>::new_limited_range(reuse_policy, min_id, max_id))
+    }
+
+    pub fn new_with_ranges(reuse_policy: ReusePolicy, ranges: Intervals<T>, range_order: RangeOrder) -> Self {
+        Self::from_manager(IdManager::<T, S>::new_with_ranges(reuse_policy, ranges, range_order))
+    }
+
+    pub fn from_snapshot(snapshot: IdManagerSnapshot<T>) -> Result<Self, ParseError> {
+        Ok(Self::from_manager(IdManager::<T, S>::from_snapshot(snapshot)?))
+    }
+
+    pub fn snapshot(&self) -> IdManagerSnapshot<T> {
+        let locked = self.lock();
+
+        locked.snapshot()
+    }
+
+    fn from_manager(manager: IdManager<T, S>) -> Self {
+        let manager = Arc::new(Mutex::new(manager));
+
+        let observers = Arc::new(Mutex::new(Observers { next_id: 0, observers: Vec::new() }));
+
+        let watermarks = Arc::new(Mutex::new(Vec::new()));
+
+        let alerts = Arc::new(Mutex::new(AlertQueue { pending: VecDeque::new(), delivering: false }));
+
+        let leak_tracker = Arc::new(Mutex::new(None));
+
+        let leases = Arc::new(Mutex::new(LeaseTable::new()));
+
+        ThreadSafeIdManager { manager, observers, watermarks, alerts, leak_tracker, leases, parent: None }
+    }
+
+    pub fn lease_child(&self, size: u128) -> Option<Self> {
+        let reuse_policy = self.lock().reuse_policy();
+
+        Self::lease_from(Arc::new(ParentManager(self.clone())), reuse_policy, size)
+    }
+
+    pub fn lease_from(parent: Arc<dyn BlockSource<T>>, reuse_policy: ReusePolicy, block_size: u128) -> Option<Self> {
+        let (lower, upper) = parent.lease_block(block_size)?;
+
+        let lease = ParentLease {
+            parent,
+            block_size,
+            blocks: Mutex::new(vec![(lower, upper)]),
+            revoked: Mutex::new(Intervals::new()),
+        };
+
+        let mut child = Self::new_limited_range(reuse_policy, lower, upper);
+
+        child.parent = Some(Arc::new(lease));
+
+        Some(child)
+    }
+
+    pub fn lease_block(&self, size: u128) -> Option<(T, T)> {
+        let mut events = Vec::new();
+
+        let block = {
+            let mut locked = self.lock();
+
+            let block = locked.allocate_block(size);
+
+            if let Some((lower, upper)) = block {
+                events.push(IdManagerEvent::BlockAllocated(lower, upper));
+
+                if !locked.can_allocate() {
+                    events.push(IdManagerEvent::Exhausted);
+                }
+            }
+
+            self.check_watermarks(&locked);
+
+            block
+        };
+
+        self.notify(&events);
+
+        self.alert();
+
+        block
+    }
+
+    // the ids may still be held by SmartIds so only blocks that are known to be leased, such
+    // as those held by a child, can be returned
+
+    pub(crate) fn return_block(&self, lower: T, upper: T) {
+        {
+            let mut locked = self.lock();
+
+            locked.free_block(lower, upper);
+
+            self.check_watermarks(&locked);
+        }
+
+        self.notify(&[IdManagerEvent::BlockFreed(lower, upper)]);
+
+        self.alert();
+    }
+
+    // stops a child allocating from a block that it has lost, e.g. because the lease on it
+    // expired. Ids from the block that are still held are forgotten when they're freed.
+
+    pub(crate) fn revoke_block(&self, lower: T, upper: T) {
+        let Some(parent) = &self.parent else {
+            panic!("manager was not leased from a parent");
+        };
+
+        parent.blocks.lock().unwrap().retain(|block| *block != (lower, upper));
+
+        let mut locked = self.lock();
+
+        locked.retire_interval(lower, upper);
+
+        parent.revoked.lock().unwrap().insert_interval(lower, upper);
+    }
+
+    fn is_revoked(&self, id: T) -> bool {
+        self.parent.as_ref().is_some_and(|parent| parent.is_revoked(id))
+    }
+
+    pub fn add_observer<O: IdManagerObserver<T> + 'static>(&self, observer: O) -> ObserverId {
+        let mut observers = self.observers.lock().unwrap();
+
+        let id = ObserverId(observers.next_id);
+
+        observers.next_id += 1;
+
+        observers.observers.push((id, Arc::new(observer)));
+
+        id
+    }
+
+    // an observer that is being notified by another thread may still be called once more
+
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        let mut observers = self.observers.lock().unwrap();
+
+        let count = observers.observers.len();
+
+        observers.observers.retain(|(observer_id, _)| *observer_id != id);
+
+        observers.observers.len() != count
+    }
+
+    pub fn add_capacity_threshold<F>(&self, threshold: CapacityThreshold, callback: F)
+    where
+        F: Fn(&CapacityAlert) + Send + Sync + 'static,
+    {
+        let watermark = Watermark::new(threshold);
+
+        {
+            let locked = self.lock();
+
+            self.watermarks.lock().unwrap().push((watermark, Arc::new(callback)));
+
+            self.check_watermarks(&locked);
+        }
+
+        self.alert();
+    }
+
+    // leak tracking captures a backtrace for every allocation and so is only intended for debugging
+
+    pub fn enable_leak_tracking(&self) {
+        let mut tracker = self.leak_tracker.lock().unwrap();
+
+        if tracker.is_none() {
+            *tracker = Some(LeakTracker::new());
+        }
+    }
+
+    pub fn report_live_ids(&self, held_for_at_least: Duration) -> Vec<LiveId<T>> {
+        match self.leak_tracker.lock().unwrap().as_ref() {
+            Some(tracker) => tracker.report(held_for_at_least),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        let locked = self.lock();
+
+        locked.dump()
+    }
+
+    pub fn can_allocate(&self) -> bool {
+        let locked = self.lock();
+
+        locked.can_allocate()
+    }
+
+    pub fn stats(&self) -> IdManagerStats {
+        let locked = self.lock();
+
+        locked.stats()
+    }
+
+    pub fn reset_peak_allocated(&self) {
+        let mut locked = self.lock();
+
+        locked.reset_peak_allocated();
+    }
 
-        ThreadSafeIdManager { manager }
+    // operations are recorded whilst the lock is held so a trace taken from several threads
+    // is in the order that the operations were made
+
+    pub fn start_trace(&self) {
+        let mut locked = self.lock();
+
+        locked.start_trace();
+    }
+
+    pub fn take_trace(&self) -> Option<Trace<T>> {
+        let mut locked = self.lock();
+
+        locked.take_trace()
+    }
+
+    pub(crate) fn allocate(&self) -> T {
+        match self.try_allocate() {
+            Some(id) => id,
+            None => panic!("No Ids available"),
+        }
+    }
+
+    // checks for a free id whilst holding the lock so that another thread can't take the last
+    // one in between
+
+    pub(crate) fn try_allocate(&self) -> Option<T> {
+        self.take_id(false)
+    }
+
+    fn take_id(&self, reserve: bool) -> Option<T> {
+        if let Some(parent) = &self.parent {
+            // we lease more ids from our parent without holding our own lock so that the
+            // parent's observers are not called whilst we are locked
+
+            if !self.can_allocate() {
+                if let Some((lower, upper)) = parent.lease_block() {
+                    self.lock().add_range(lower, upper).unwrap();
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+
+        let id = {
+            let mut locked = self.lock();
+
+            if !locked.can_allocate() {
+                return None;
+            }
+
+            let wrap_arounds = locked.wrap_arounds();
+
+            let id = if reserve {
+                let id = locked.reserve();
+
+                events.push(IdManagerEvent::Reserved(id));
+
+                id
+            } else {
+                let id = locked.allocate();
+
+                events.push(IdManagerEvent::Allocated(id));
+
+                id
+            };
+
+            if locked.wrap_arounds() != wrap_arounds {
+                events.push(IdManagerEvent::WrappedAround);
+            }
+
+            if !locked.can_allocate() {
+                events.push(IdManagerEvent::Exhausted);
+            }
+
+            if !reserve {
+                self.track(|tracker| tracker.allocated(id));
+            }
+
+            self.check_watermarks(&locked);
+
+            id
+        };
+
+        self.notify(&events);
+
+        self.alert();
+
+        Some(id)
+    }
+
+    pub fn allocate_id(&self) -> SmartId<T, S> {
+        SmartId::new(self.clone())
+    }
+
+    pub fn try_allocate_id(&self) -> Option<SmartId<T, S>> {
+        let id = self.try_allocate()?;
+
+        Some(SmartId::from_id(self.clone(), id))
+    }
+
+    // the id is returned to the front of the free ids if the SmartId is dropped without being
+    // committed
+
+    pub fn reserve_id(&self) -> SmartId<T, S> {
+        match self.try_reserve_id() {
+            Some(id) => id,
+            None => panic!("No Ids available"),
+        }
+    }
+
+    pub fn try_reserve_id(&self) -> Option<SmartId<T, S>> {
+        let id = self.take_id(true)?;
+
+        Some(SmartId::reserved(self.clone(), id))
+    }
+
+    pub(crate) fn commit(&self, id: T) {
+        {
+            let mut locked = self.lock();
+
+            if self.is_revoked(id) {
+                return;
+            }
+
+            locked.commit(id);
+
+            self.track(|tracker| tracker.allocated(id));
+
+            self.check_watermarks(&locked);
+        }
+
+        self.notify(&[IdManagerEvent::Allocated(id)]);
+
+        self.alert();
+    }
+
+    pub(crate) fn abort(&self, id: T) {
+        {
+            let mut locked = self.lock();
+
+            if self.is_revoked(id) {
+                return;
+            }
+
+            locked.abort(id);
+
+            self.check_watermarks(&locked);
+        }
+
+        self.notify(&[IdManagerEvent::Aborted(id)]);
+
+        self.alert();
+    }
+
+    pub fn allocate_lease(&self, ttl: Duration) -> TtlLease<T, S> {
+        let id = self.allocate();
+
+        let generation = self.leases().insert(id, ttl_lease::expiry(ttl));
+
+        TtlLease::new(self.clone(), id, generation, ttl)
+    }
+
+    // frees the ids of the leases that expired at or before now, returning how many there were
+
+    pub fn reap_expired(&self, now: Instant) -> usize {
+        let expired = self.leases().remove_expired(now);
+
+        for id in expired.iter() {
+            self.notify(&[IdManagerEvent::Expired(*id)]);
+
+            self.free(*id);
+        }
+
+        expired.len()
+    }
+
+    pub fn start_lease_reaper(&self, period: Duration) -> LeaseReaper {
+        LeaseReaper::start(self.clone(), period)
+    }
+
+    pub(crate) fn free(&self, id: T) {
+        {
+            let mut locked = self.lock();
+
+            self.track(|tracker| tracker.freed(id));
+
+            if self.is_revoked(id) {
+                return;
+            }
+
+            locked.free(id);
+
+            self.check_watermarks(&locked);
+        }
+
+        self.notify(&[IdManagerEvent::Freed(id)]);
+
+        self.alert();
+    }
+
+    pub fn mark_value_as_used(&self, id: T) {
+        self.mark_interval_as_used(id, id);
+    }
+
+    pub fn mark_interval_as_used(&self, lower: T, upper: T) {
+        let mut events = vec![IdManagerEvent::MarkedAsUsed(lower, upper)];
+
+        {
+            let mut locked = self.lock();
+
+            let could_allocate = locked.can_allocate();
+
+            locked.mark_interval_as_used(lower, upper);
+
+            if could_allocate && !locked.can_allocate() {
+                events.push(IdManagerEvent::Exhausted);
+            }
+
+            self.check_watermarks(&locked);
+        }
+
+        self.notify(&events);
+
+        self.alert();
+    }
+
+    pub fn grow_range(&self, new_min_id: T, new_max_id: T) -> Result<(), RangeError> {
+        self.resize_range(|manager| manager.grow_range(new_min_id, new_max_id))
+    }
+
+    pub fn shrink_range(&self, new_min_id: T, new_max_id: T) -> Result<(), RangeError> {
+        self.resize_range(|manager| manager.shrink_range(new_min_id, new_max_id))
+    }
+
+    fn resize_range<F>(&self, resize: F) -> Result<(), RangeError>
+    where
+        F: FnOnce(&mut IdManager<T, S>) -> Result<(), RangeError>,
+    {
+        {
+            let mut locked = self.lock();
+
+            resize(&mut locked)?;
+
+            self.check_watermarks(&locked);
+        }
+
+        self.alert();
+
+        Ok(())
     }
 
-    pub fn new_limited_range(reuse_policy: ReusePolicy, min_id: T, max_id: T) -> Self {
-        let manager = Arc::new(Mutex::new(IdManager::<T>::new_limited_range(reuse_policy, min_id, max_id)));
+    fn notify(&self, events: &[IdManagerEvent<T>]) {
+        // take a copy of the observers so that none of our locks are held whilst they run
+
+        let observers: Vec<Arc<dyn IdManagerObserver<T>>> =
+            self.observers.lock().unwrap().observers.iter().map(|(_, observer)| observer.clone()).collect();
+
+        for event in events {
+            for observer in observers.iter() {
+                observer.on_event(event);
+            }
+        }
+    }
+
+    // must be called whilst the manager is locked, otherwise a free that is recorded late
+    // could remove the record of the same id being allocated again by another thread
+
+    fn track<F: FnOnce(&mut LeakTracker<T>)>(&self, update: F) {
+        if let Some(tracker) = self.leak_tracker.lock().unwrap().as_mut() {
+            update(tracker);
+        }
+    }
+
+    // must be called whilst the manager is locked so that alerts are queued in order
+
+    fn check_watermarks(&self, manager: &IdManager<T, S>) {
+        let mut watermarks = self.watermarks.lock().unwrap();
+
+        // this is called on every change so it mustn't cost more than the change itself
+
+        if watermarks.is_empty() {
+            return;
+        }
+
+        let capacity = manager.capacity();
+
+        let available = manager.available();
+
+        for (watermark, callback) in watermarks.iter_mut() {
+            if let Some(alert) = watermark.check(capacity, available, || manager.stats()) {
+                self.alerts.lock().unwrap().pending.push_back((callback.clone(), alert));
+            }
+        }
+    }
+
+    // if another thread, or a callback that called back into the manager, is already delivering
+    // then it will deliver any queued alerts once it has delivered the earlier ones
+
+    fn alert(&self) {
+        {
+            let mut queue = self.alerts.lock().unwrap();
+
+            if queue.delivering || queue.pending.is_empty() {
+                return;
+            }
+
+            queue.delivering = true;
+        }
+
+        loop {
+            let next = {
+                let mut queue = self.alerts.lock().unwrap();
+
+                let next = queue.pending.pop_front();
+
+                queue.delivering = next.is_some();
+
+                next
+            };
+
+            let Some((callback, alert)) = next else {
+                return;
+            };
+
+            callback(&alert);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, IdManager<T, S>> {
+        self.manager.lock().unwrap()
+    }
+
+    pub(crate) fn leases(&self) -> MutexGuard<'_, LeaseTable<T>> {
+        self.leases.lock().unwrap()
+    }
+}
+
+// The parent of a child from lease_child(), it isn't a public BlockSource as anything could
+// then return blocks to the manager
+
+struct ParentManager<T: IdType, S: FreeSet<T>>(ThreadSafeIdManager<T, S>);
+
+impl<T: IdType, S: FreeSet<T>> BlockSource<T> for ParentManager<T, S> {
+    fn lease_block(&self, size: u128) -> Option<(T, T)> {
+        self.0.lease_block(size)
+    }
+
+    fn return_block(&self, lower: T, upper: T) {
+        self.0.return_block(lower, upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitmap_free_set::BitmapFreeSet;
+    use crate::reuse_policy::ReusePolicy::ReuseFast;
+    use crate::reuse_policy::ReusePolicy::ReuseSlow;
+
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let _manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+    }
+
+    #[test]
+    fn test_new_for_all_supported_types() {
+        {
+            let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+            assert_eq!(manager.dump(), "[0,255]");
+        }
+        {
+            let manager = ThreadSafeIdManager::<u16>::new(ReuseSlow);
+
+            assert_eq!(manager.dump(), "[0,65535]");
+        }
+        {
+            let manager = ThreadSafeIdManager::<u32>::new(ReuseSlow);
+
+            assert_eq!(manager.dump(), "[0,4294967295]");
+        }
+        {
+            let manager = ThreadSafeIdManager::<u64>::new(ReuseSlow);
+
+            assert_eq!(manager.dump(), "[0,18446744073709551615]");
+        }
+        {
+            let manager = ThreadSafeIdManager::<u128>::new(ReuseSlow);
+
+            assert_eq!(manager.dump(), "[0,340282366920938463463374607431768211455]");
+        }
+        {
+            let manager = ThreadSafeIdManager::<usize>::new(ReuseSlow);
+
+            assert_eq!(manager.dump(), "[0,18446744073709551615]");
+        }
+    }
+
+    #[test]
+    fn test_new_limited_range() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+
+        assert_eq!(manager.dump(), "[10,50]");
+    }
+
+    #[test]
+    fn test_can_allocate() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.can_allocate(), true);
+    }
+
+    #[test]
+    fn test_allocate() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.allocate(), 0);
+
+        assert_eq!(manager.dump(), "[1,255]");
+    }
+
+    #[test]
+    fn test_try_allocate_id() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 11);
+
+        let id1 = manager.try_allocate_id().unwrap();
+        let id2 = manager.try_allocate_id().unwrap();
+
+        assert_eq!((*id1.value(), *id2.value()), (10, 11));
+
+        assert_eq!(manager.try_allocate_id().is_none(), true);
+
+        drop(id1);
+
+        assert_eq!(*manager.try_allocate_id().unwrap().value(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "No Ids available")]
+    fn test_allocate_when_exhausted() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 10);
+
+        manager.allocate();
+        manager.allocate();
+    }
+
+    #[test]
+    fn test_allocate_all_ids_and_wrap() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        for i in 0..u8::MAX {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "[255]");
+
+        assert_eq!(manager.allocate(), 255);
+
+        assert_eq!(manager.can_allocate(), false);
+
+        assert_eq!(manager.dump(), "");
+
+        for i in 0..10 {
+            manager.free(i);
+        }
+
+        assert_eq!(manager.dump(), "[0,9]");
+
+        for i in 0..10 {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "");
+    }
+
+    #[test]
+    fn test_allocate_all_ids_and_wrap_limited_range() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        for i in 10..50 {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "[50]");
+
+        assert_eq!(manager.allocate(), 50);
+
+        assert_eq!(manager.can_allocate(), false);
+
+        assert_eq!(manager.dump(), "");
+
+        for i in 10..20 {
+            manager.free(i);
+        }
+
+        assert_eq!(manager.dump(), "[10,19]");
+
+        for i in 10..20 {
+            assert_eq!(manager.allocate(), i);
+        }
+        assert_eq!(manager.dump(), "");
+    }
+
+    #[test]
+    fn test_free() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.allocate(), 0);
+
+        assert_eq!(manager.dump(), "[1,255]");
+
+        manager.free(0);
+
+        assert_eq!(manager.dump(), "[0,255]");
+    }
+
+    #[test]
+    #[should_panic(expected = "id is not currently allocated")]
+    fn test_free_id_not_allocated() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        manager.free(0);
+
+        assert_eq!(manager.dump(), "[0,255]");
+    }
+
+    #[test]
+    fn test_create_one_smart_id() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        {
+            let _id = manager.allocate_id();
+
+            assert_eq!(manager.dump(), "[1,255]");
+        }
+
+        assert_eq!(manager.dump(), "[0,255]");
+    }
+
+    #[test]
+    fn test_create_multiple_smart_ids() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        {
+            let id1 = manager.allocate_id();
+
+            let expected_id1: u8 = 0;
+
+            assert_eq!(id1.value(), &expected_id1);
+
+            assert_eq!(manager.dump(), "[1,255]");
+
+            {
+                let mut id2 = manager.allocate_id();
+
+                let expected_id2: u8 = 1;
+
+                assert_eq!(id2.value(), &expected_id2);
+
+                assert_eq!(manager.dump(), "[2,255]");
+
+                id2.release();
+
+                {
+                    let id3 = manager.allocate_id();
+
+                    let expected_id: u8 = 2;
+
+                    assert_eq!(id3.value(), &expected_id);
+
+                    assert_eq!(manager.dump(), "[3,255]");
+                }
+            }
+
+            assert_eq!(manager.dump(), "[2,255]");
+        }
+
+        assert_eq!(manager.dump(), "[0], [2,255]");
+    }
+
+    #[test]
+    fn test_reuse_fast() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseFast);
+
+        for i in 0..10 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.dump(), "[10,255]");
+
+        manager.free(2);
+        manager.free(6);
+        manager.free(7);
+        manager.free(4);
+
+        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+
+        assert_eq!(manager.allocate(), 2);
+        assert_eq!(manager.allocate(), 4);
+        assert_eq!(manager.allocate(), 6);
+        assert_eq!(manager.allocate(), 7);
+        assert_eq!(manager.allocate(), 10);
+
+        assert_eq!(manager.dump(), "[11,255]");
+    }
+
+    #[test]
+    fn test_reuse_slow() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        for i in 0..10 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.dump(), "[10,255]");
+
+        manager.free(2);
+        manager.free(6);
+        manager.free(7);
+        manager.free(4);
+
+        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+
+        for i in 10..255 {
+            assert_eq!(manager.allocate(), i);
+        }
+
+        assert_eq!(manager.dump(), "[2], [4], [6,7], [255]");
+
+        assert_eq!(manager.allocate(), 255);
+
+        assert_eq!(manager.allocate(), 2);
+        assert_eq!(manager.allocate(), 4);
+        assert_eq!(manager.allocate(), 6);
+        assert_eq!(manager.allocate(), 7);
+
+        assert_eq!(manager.dump(), "");
+    }
+
+    #[test]
+    fn test_mark_value_as_used() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        manager.mark_value_as_used(0);
+
+        assert_eq!(manager.dump(), "[1,255]");
+
+        manager.mark_value_as_used(2);
+
+        assert_eq!(manager.dump(), "[1], [3,255]");
+
+        manager.mark_value_as_used(4);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,255]");
+
+        manager.mark_value_as_used(10);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,255]");
+
+        manager.mark_value_as_used(255);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,254]");
+
+        manager.mark_value_as_used(253);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,252], [254]");
+
+        manager.mark_value_as_used(251);
+
+        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,250], [252], [254]");
+    }
+
+    #[test]
+    fn test_mark_interval_as_used() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+
+        assert_eq!(manager.dump(), "[0,255]");
+
+        manager.mark_interval_as_used(0, 1);
+
+        assert_eq!(manager.dump(), "[2,255]");
+
+        manager.mark_interval_as_used(4, 10);
+
+        assert_eq!(manager.dump(), "[2,3], [11,255]");
+
+        manager.mark_interval_as_used(3, 12);
+
+        assert_eq!(manager.dump(), "[2], [13,255]");
+
+        manager.mark_interval_as_used(254, 255);
+
+        assert_eq!(manager.dump(), "[2], [13,253]");
+
+        manager.mark_interval_as_used(250, 251);
+
+        assert_eq!(manager.dump(), "[2], [13,249], [252,253]");
+
+        manager.mark_interval_as_used(0, 255);
+
+        assert_eq!(manager.dump(), "");
+    }
+
+    #[test]
+    fn test_observer_is_notified_of_events() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 12);
+
+        let events = Arc::new(Mutex::new(Vec::<IdManagerEvent<u8>>::new()));
+
+        let observed = events.clone();
+
+        manager.add_observer(move |event: &IdManagerEvent<u8>| observed.lock().unwrap().push(*event));
+
+        manager.mark_value_as_used(11);
+
+        {
+            let _id1 = manager.allocate_id();
+            let _id2 = manager.allocate_id();
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            IdManagerEvent::MarkedAsUsed(11, 11),
+            IdManagerEvent::Allocated(10),
+            IdManagerEvent::Allocated(12),
+            IdManagerEvent::WrappedAround,
+            IdManagerEvent::Exhausted,
+            IdManagerEvent::Freed(12),
+            IdManagerEvent::Freed(10)]);
+    }
+
+    #[test]
+    fn test_observer_is_notified_when_marking_exhausts_ids() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let events = Arc::new(Mutex::new(Vec::<IdManagerEvent<u8>>::new()));
+
+        let observed = events.clone();
+
+        manager.add_observer(move |event: &IdManagerEvent<u8>| observed.lock().unwrap().push(*event));
+
+        manager.mark_interval_as_used(10, 20);
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            IdManagerEvent::MarkedAsUsed(10, 20),
+            IdManagerEvent::Exhausted]);
+    }
+
+    #[test]
+    fn test_observer_can_call_back_into_manager() {
+        let manager = Arc::new(ThreadSafeIdManager::<u8>::new(ReuseSlow));
+
+        let dumps = Arc::new(Mutex::new(Vec::<String>::new()));
+
+        let observed = dumps.clone();
+
+        // a strong clone of the manager would keep it alive through its own observers
+
+        let observed_manager = Arc::downgrade(&manager);
+
+        manager.add_observer(move |_event: &IdManagerEvent<u8>| {
+            if let Some(manager) = observed_manager.upgrade() {
+                observed.lock().unwrap().push(manager.dump());
+            }
+        });
+
+        {
+            let _id = manager.allocate_id();
+        }
+
+        assert_eq!(*dumps.lock().unwrap(), vec!["[1,255]", "[0,255]"]);
+    }
+
+    #[test]
+    fn test_remove_observer() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let events = Arc::new(Mutex::new(Vec::<IdManagerEvent<u8>>::new()));
+
+        let observed = events.clone();
+
+        let first = manager.add_observer(move |event: &IdManagerEvent<u8>| observed.lock().unwrap().push(*event));
+
+        let second = manager.add_observer(|_event: &IdManagerEvent<u8>| {});
+
+        assert_ne!(first, second);
+
+        manager.mark_value_as_used(10);
+
+        assert_eq!(manager.remove_observer(first), true);
+        assert_eq!(manager.remove_observer(first), false);
+
+        manager.mark_value_as_used(11);
+
+        assert_eq!(*events.lock().unwrap(), vec![IdManagerEvent::MarkedAsUsed(10, 10)]);
+
+        // the removed observer has been dropped
+
+        assert_eq!(Arc::strong_count(&events), 1);
+    }
+
+    #[test]
+    fn test_stats() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        {
+            let _id1 = manager.allocate_id();
+            let _id2 = manager.allocate_id();
+
+            manager.mark_interval_as_used(20, 29);
+
+            let stats = manager.stats();
+
+            assert_eq!(stats.capacity, 41);
+            assert_eq!(stats.allocated, 12);
+            assert_eq!(stats.free_intervals, 2);
+            assert_eq!(stats.largest_free_run, 21);
+        }
+
+        let stats = manager.stats();
+
+        assert_eq!(stats.allocated, 10);
+        assert_eq!(stats.peak_allocated, 12);
+
+        manager.reset_peak_allocated();
+
+        assert_eq!(manager.stats().peak_allocated, 10);
+    }
+
+    #[test]
+    fn test_capacity_threshold() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 0, 99);
+
+        let alerts = Arc::new(Mutex::new(Vec::<CapacityAlert>::new()));
+
+        let observed = alerts.clone();
+
+        manager.add_capacity_threshold(CapacityThreshold::Percent { low: 10, recovered: 20 }, move |alert: &CapacityAlert| observed.lock().unwrap().push(*alert));
+
+        manager.mark_interval_as_used(0, 89);
+
+        assert_eq!(alerts.lock().unwrap().len(), 0);
+
+        let mut ids = Vec::new();
+
+        ids.push(manager.allocate_id());
+
+        assert_eq!(alerts.lock().unwrap().len(), 1);
+
+        assert!(matches!(alerts.lock().unwrap()[0], CapacityAlert::Low(stats) if stats.available() == 9));
+
+        ids.push(manager.allocate_id());
+
+        assert_eq!(alerts.lock().unwrap().len(), 1);
+
+        for id in 0..10 {
+            manager.free(id);
+        }
+
+        assert_eq!(alerts.lock().unwrap().len(), 1);
+
+        ids.clear();
+
+        assert_eq!(alerts.lock().unwrap().len(), 2);
+
+        assert!(matches!(alerts.lock().unwrap()[1], CapacityAlert::Recovered(stats) if stats.available() == 20));
+    }
+
+    #[test]
+    fn test_capacity_alerts_are_delivered_in_order() {
+        let manager = Arc::new(ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 0, 99));
+
+        let alerts = Arc::new(Mutex::new(Vec::<CapacityAlert>::new()));
+
+        let observed = alerts.clone();
+
+        let observed_manager = Arc::downgrade(&manager);
 
-        ThreadSafeIdManager { manager }
-    }
+        // the Recovered is raised by the callback for the Low, before the Low has been recorded
 
-    pub fn dump(&self) -> String {
-        let locked = self.lock();
+        manager.add_capacity_threshold(CapacityThreshold::Percent { low: 10, recovered: 20 }, move |alert: &CapacityAlert| {
+            if let (CapacityAlert::Low(_), Some(manager)) = (alert, observed_manager.upgrade()) {
+                for id in 0..20 {
+                    manager.free(id);
+                }
+            }
 
-        locked.dump()
-    }
+            observed.lock().unwrap().push(*alert);
+        });
 
-    pub fn can_allocate(&self) -> bool {
-        let locked = self.lock();
+        manager.mark_interval_as_used(0, 89);
 
-        locked.can_allocate()
-    }
+        let _id = manager.allocate_id();
 
-    fn allocate(&self) -> T {
-        let mut locked = self.lock();
+        let alerts = alerts.lock().unwrap();
 
-        locked.allocate()
-    }
+        assert_eq!(alerts.len(), 2);
 
-    pub fn allocate_id(&self) -> SmartId<T> {
-        SmartId::new(self.manager.clone())
+        assert!(matches!(alerts[0], CapacityAlert::Low(stats) if stats.available() == 9));
+        assert!(matches!(alerts[1], CapacityAlert::Recovered(stats) if stats.available() == 20));
     }
 
-    fn free(&self, id: T) {
-        let mut locked = self.lock();
+    #[test]
+    fn test_capacity_threshold_fires_when_added_if_already_low() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 0, 99);
 
-        locked.free(id)
-    }
+        manager.mark_interval_as_used(0, 95);
 
-    pub fn mark_value_as_used(&self, id: T) {
-        let mut locked = self.lock();
+        let alerts = Arc::new(Mutex::new(Vec::<CapacityAlert>::new()));
 
-        locked.mark_value_as_used(id);
-    }
+        let observed = alerts.clone();
 
-    pub fn mark_interval_as_used(&self, lower: T, upper: T) {
-        let mut locked = self.lock();
+        manager.add_capacity_threshold(CapacityThreshold::Available { low: 5, recovered: 10 }, move |alert: &CapacityAlert| observed.lock().unwrap().push(*alert));
 
-        locked.mark_interval_as_used(lower, upper);
+        assert_eq!(alerts.lock().unwrap().len(), 1);
     }
 
-    fn lock(&self) -> MutexGuard<IdManager<T>> {
-        self.manager.lock().unwrap()
-    }
-}
+    #[test]
+    fn test_report_live_ids() {
+        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
 
-#[cfg(test)]
-mod tests {
-    use crate::reuse_policy::ReusePolicy::ReuseFast;
-    use crate::reuse_policy::ReusePolicy::ReuseSlow;
+        let _untracked = manager.allocate_id();
 
-    use super::*;
+        assert_eq!(manager.report_live_ids(Duration::ZERO).len(), 0);
 
-    #[test]
-    fn test_new() {
-        let _manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
-    }
+        manager.enable_leak_tracking();
 
-    #[test]
-    fn test_new_for_all_supported_types() {
-        {
-            let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+        let id1 = manager.allocate_id();
 
-            assert_eq!(manager.dump(), "[0,255]");
-        }
-        {
-            let manager = ThreadSafeIdManager::<u16>::new(ReuseSlow);
+        let mut id2 = manager.allocate_id();
 
-            assert_eq!(manager.dump(), "[0,65535]");
-        }
-        {
-            let manager = ThreadSafeIdManager::<u32>::new(ReuseSlow);
+        id2.release();
 
-            assert_eq!(manager.dump(), "[0,4294967295]");
-        }
-        {
-            let manager = ThreadSafeIdManager::<u64>::new(ReuseSlow);
+        drop(id2);
 
-            assert_eq!(manager.dump(), "[0,18446744073709551615]");
-        }
         {
-            let manager = ThreadSafeIdManager::<u128>::new(ReuseSlow);
-
-            assert_eq!(manager.dump(), "[0,340282366920938463463374607431768211455]");
+            let _id3 = manager.allocate_id();
         }
-        {
-            let manager = ThreadSafeIdManager::<usize>::new(ReuseSlow);
 
-            assert_eq!(manager.dump(), "[0,18446744073709551615]");
-        }
-    }
+        let live_ids: Vec<u8> = manager.report_live_ids(Duration::ZERO).iter().map(|live_id| live_id.id).collect();
 
-    #[test]
-    fn test_new_limited_range() {
-        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
+        assert_eq!(live_ids, vec![1, 2]);
 
-        assert_eq!(manager.dump(), "[10,50]");
-    }
+        assert_eq!(manager.report_live_ids(Duration::from_secs(3600)).len(), 0);
 
-    #[test]
-    fn test_can_allocate() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+        drop(id1);
 
-        assert_eq!(manager.can_allocate(), true);
+        let live_ids: Vec<u8> = manager.report_live_ids(Duration::ZERO).iter().map(|live_id| live_id.id).collect();
+
+        assert_eq!(live_ids, vec![2]);
     }
 
     #[test]
-    fn test_allocate() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+    fn test_report_live_ids_when_ids_are_reused_concurrently() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 13);
 
-        assert_eq!(manager.allocate(), 0);
+        manager.enable_leak_tracking();
 
-        assert_eq!(manager.dump(), "[1,255]");
-    }
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let manager = manager.clone();
 
-    #[test]
-    fn test_allocate_all_ids_and_wrap() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        drop(manager.allocate_id());
+                    }
+                })
+            })
+            .collect();
 
-        for i in 0..u8::MAX {
-            assert_eq!(manager.allocate(), i);
+        for thread in threads {
+            thread.join().unwrap();
         }
-        assert_eq!(manager.dump(), "[255]");
-
-        assert_eq!(manager.allocate(), 255);
-
-        assert_eq!(manager.can_allocate(), false);
 
-        assert_eq!(manager.dump(), "");
+        assert_eq!(manager.report_live_ids(Duration::ZERO).len(), 0);
 
-        for i in 0..10 {
-            manager.free(i);
-        }
+        let _ids: Vec<_> = (0..4).map(|_| manager.allocate_id()).collect();
 
-        assert_eq!(manager.dump(), "[0,9]");
+        let live_ids: Vec<u8> = manager.report_live_ids(Duration::ZERO).iter().map(|live_id| live_id.id).collect();
 
-        for i in 0..10 {
-            assert_eq!(manager.allocate(), i);
-        }
-        assert_eq!(manager.dump(), "");
+        assert_eq!(live_ids, vec![10, 11, 12, 13]);
     }
 
     #[test]
-    fn test_allocate_all_ids_and_wrap_limited_range() {
-        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
-
-        for i in 10..50 {
-            assert_eq!(manager.allocate(), i);
-        }
-        assert_eq!(manager.dump(), "[50]");
+    fn test_resize_range_does_not_invalidate_smart_ids() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 12);
 
-        assert_eq!(manager.allocate(), 50);
+        let id1 = manager.allocate_id();
+        let id2 = manager.allocate_id();
+        let id3 = manager.allocate_id();
 
         assert_eq!(manager.can_allocate(), false);
 
-        assert_eq!(manager.dump(), "");
+        assert_eq!(manager.grow_range(10, 20), Ok(()));
 
-        for i in 10..20 {
-            manager.free(i);
+        {
+            let id4 = manager.allocate_id();
+
+            assert_eq!(id4.value(), &13);
         }
 
-        assert_eq!(manager.dump(), "[10,19]");
+        assert_eq!(manager.shrink_range(10, 11), Err(RangeError::IdsInUse));
+
+        drop(id3);
+
+        assert_eq!(manager.shrink_range(10, 11), Ok(()));
 
-        for i in 10..20 {
-            assert_eq!(manager.allocate(), i);
-        }
         assert_eq!(manager.dump(), "");
+
+        drop(id1);
+        drop(id2);
+
+        assert_eq!(manager.dump(), "[10,11]");
     }
 
     #[test]
-    fn test_free() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+    fn test_resize_range_checks_capacity_thresholds() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 0, 9);
 
-        assert_eq!(manager.allocate(), 0);
+        let alerts = Arc::new(Mutex::new(Vec::<CapacityAlert>::new()));
 
-        assert_eq!(manager.dump(), "[1,255]");
+        let observed = alerts.clone();
 
-        manager.free(0);
+        manager.add_capacity_threshold(CapacityThreshold::Available { low: 5, recovered: 5 }, move |alert: &CapacityAlert| observed.lock().unwrap().push(*alert));
 
-        assert_eq!(manager.dump(), "[0,255]");
-    }
+        manager.mark_interval_as_used(0, 5);
 
-    #[test]
-    #[should_panic(expected = "id is not currently allocated")]
-    fn test_free_id_not_allocated() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+        assert_eq!(alerts.lock().unwrap().len(), 1);
 
-        assert_eq!(manager.dump(), "[0,255]");
+        assert_eq!(manager.grow_range(0, 19), Ok(()));
 
-        manager.free(0);
+        assert_eq!(alerts.lock().unwrap().len(), 2);
 
-        assert_eq!(manager.dump(), "[0,255]");
+        assert!(matches!(alerts.lock().unwrap()[1], CapacityAlert::Recovered(_)));
     }
 
     #[test]
-    fn test_create_one_smart_id() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+    fn test_new_with_ranges() {
+        let mut ranges = Intervals::<u16>::new();
 
-        {
-            let _id = manager.allocate_id();
+        ranges.insert_interval(100, 199);
+        ranges.insert_interval(1000, 1999);
 
-            assert_eq!(manager.dump(), "[1,255]");
-        }
+        let manager = ThreadSafeIdManager::<u16>::new_with_ranges(ReuseSlow, ranges, RangeOrder::Descending);
 
-        assert_eq!(manager.dump(), "[0,255]");
+        let id = manager.allocate_id();
+
+        assert_eq!(id.value(), &1000);
+
+        assert_eq!(manager.dump(), "[100,199], [1001,1999]");
     }
 
     #[test]
-    fn test_create_multiple_smart_ids() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
-
-        assert_eq!(manager.dump(), "[0,255]");
+    fn test_lease_child() {
+        let parent = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
 
         {
-            let id1 = manager.allocate_id();
+            let child = parent.lease_child(5).unwrap();
 
-            let expected_id1: u8 = 0;
+            assert_eq!(parent.dump(), "[15,50]");
 
-            assert_eq!(id1.value(), &expected_id1);
+            assert_eq!(child.dump(), "[10,14]");
 
-            assert_eq!(manager.dump(), "[1,255]");
+            let id = child.allocate_id();
 
-            {
-                let mut id2 = manager.allocate_id();
+            assert_eq!(id.value(), &10);
 
-                let expected_id2: u8 = 1;
+            drop(child);
 
-                assert_eq!(id2.value(), &expected_id2);
+            assert_eq!(parent.dump(), "[15,50]");
+        }
 
-                assert_eq!(manager.dump(), "[2,255]");
+        assert_eq!(parent.dump(), "[10,50]");
+    }
 
-                id2.release();
+    #[test]
+    fn test_lease_child_when_parent_is_exhausted() {
+        let parent = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
 
-                {
-                    let id3 = manager.allocate_id();
+        assert_eq!(parent.lease_child(42).is_none(), true);
 
-                    let expected_id: u8 = 2;
+        assert_eq!(parent.lease_child(41).is_some(), true);
 
-                    assert_eq!(id3.value(), &expected_id);
+        assert_eq!(parent.dump(), "[10,50]");
+    }
 
-                    assert_eq!(manager.dump(), "[3,255]");
-                }
-            }
+    #[test]
+    fn test_lease_child_leases_more_blocks_when_exhausted() {
+        let parent = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
 
-            assert_eq!(manager.dump(), "[2,255]");
-        }
+        let child = parent.lease_child(2).unwrap();
 
-        assert_eq!(manager.dump(), "[0], [2,255]");
-    }
+        let other_child = parent.lease_child(2).unwrap();
 
-    #[test]
-    fn test_reuse_fast() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseFast);
+        let mut ids = Vec::new();
 
-        for i in 0..10 {
-            assert_eq!(manager.allocate(), i);
+        for _ in 0..5 {
+            ids.push(child.allocate_id());
         }
 
-        assert_eq!(manager.dump(), "[10,255]");
+        let values: Vec<u8> = ids.iter().map(|id| *id.value()).collect();
 
-        manager.free(2);
-        manager.free(6);
-        manager.free(7);
-        manager.free(4);
+        assert_eq!(values, vec![10, 11, 14, 15, 16]);
 
-        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+        assert_eq!(child.dump(), "[17]");
 
-        assert_eq!(manager.allocate(), 2);
-        assert_eq!(manager.allocate(), 4);
-        assert_eq!(manager.allocate(), 6);
-        assert_eq!(manager.allocate(), 7);
-        assert_eq!(manager.allocate(), 10);
+        assert_eq!(parent.dump(), "[18,50]");
 
-        assert_eq!(manager.dump(), "[11,255]");
+        drop(other_child);
+
+        assert_eq!(parent.dump(), "[12,13], [18,50]");
+
+        drop(child);
+
+        assert_eq!(parent.dump(), "[12,13], [18,50]");
+
+        ids.clear();
+
+        assert_eq!(parent.dump(), "[10,50]");
     }
 
     #[test]
-    fn test_reuse_slow() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+    fn test_revoke_block() {
+        let parent = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
 
-        for i in 0..10 {
-            assert_eq!(manager.allocate(), i);
-        }
+        let child = parent.lease_child(2).unwrap();
 
-        assert_eq!(manager.dump(), "[10,255]");
+        let first = child.allocate_id();
+        let second = child.allocate_id();
+        let third = child.allocate_id();
 
-        manager.free(2);
-        manager.free(6);
-        manager.free(7);
-        manager.free(4);
+        assert_eq!(child.dump(), "[13]");
 
-        assert_eq!(manager.dump(), "[2], [4], [6,7], [10,255]");
+        child.revoke_block(10, 11);
 
-        for i in 10..255 {
-            assert_eq!(manager.allocate(), i);
-        }
+        assert_eq!(child.dump(), "[13]");
+        assert_eq!(child.stats().allocated, 1);
 
-        assert_eq!(manager.dump(), "[2], [4], [6,7], [255]");
+        // the revoked ids aren't freed back into the child
 
-        assert_eq!(manager.allocate(), 255);
+        drop(first);
+        drop(second);
 
-        assert_eq!(manager.allocate(), 2);
-        assert_eq!(manager.allocate(), 4);
-        assert_eq!(manager.allocate(), 6);
-        assert_eq!(manager.allocate(), 7);
+        assert_eq!(child.dump(), "[13]");
+        assert_eq!(child.stats().allocated, 1);
 
-        assert_eq!(manager.dump(), "");
-    }
+        // the last block stays in the ranges but nothing more is allocated from it
 
-    #[test]
-    fn test_mark_value_as_used() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+        let reserved = child.reserve_id();
 
-        assert_eq!(manager.dump(), "[0,255]");
+        child.revoke_block(12, 13);
 
-        manager.mark_value_as_used(0);
+        assert_eq!(child.dump(), "");
+        assert_eq!(child.stats().capacity, 0);
+        assert_eq!(child.stats().allocated, 0);
 
-        assert_eq!(manager.dump(), "[1,255]");
+        assert_eq!(child.allocate_id().value(), &14);
 
-        manager.mark_value_as_used(2);
+        drop(third);
+        drop(reserved);
 
-        assert_eq!(manager.dump(), "[1], [3,255]");
+        assert_eq!(child.dump(), "[14,15]");
+        assert_eq!(child.stats().capacity, 2);
+        assert_eq!(child.stats().allocated, 0);
 
-        manager.mark_value_as_used(4);
+        // revoked blocks are left to whatever took them back rather than being returned
 
-        assert_eq!(manager.dump(), "[1], [3], [5,255]");
+        drop(child);
 
-        manager.mark_value_as_used(10);
+        assert_eq!(parent.dump(), "[14,50]");
+    }
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,255]");
+    #[test]
+    fn test_revoke_block_in_use() {
+        let parent = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
 
-        manager.mark_value_as_used(255);
+        let child = parent.lease_child(4).unwrap();
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,254]");
+        child.enable_leak_tracking();
 
-        manager.mark_value_as_used(253);
+        let allocated = child.allocate_id();
+        let mut committed = child.reserve_id();
+        let reserved = child.reserve_id();
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,252], [254]");
+        child.revoke_block(10, 13);
 
-        manager.mark_value_as_used(251);
+        assert_eq!(child.stats().allocated, 0);
+        assert_eq!(child.stats().reserved, 0);
+        assert_eq!(child.stats().available(), 0);
 
-        assert_eq!(manager.dump(), "[1], [3], [5,9], [11,250], [252], [254]");
+        committed.commit();
+
+        drop(allocated);
+        drop(committed);
+        drop(reserved);
+
+        assert_eq!(child.stats().allocated, 0);
+        assert_eq!(child.stats().available(), 0);
+        assert_eq!(child.report_live_ids(Duration::ZERO).len(), 0);
     }
 
     #[test]
-    fn test_mark_interval_as_used() {
-        let manager = ThreadSafeIdManager::<u8>::new(ReuseSlow);
+    fn test_lease_block_notifies_observers() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 50);
 
-        assert_eq!(manager.dump(), "[0,255]");
+        let events = Arc::new(Mutex::new(Vec::<IdManagerEvent<u8>>::new()));
 
-        manager.mark_interval_as_used(0, 1);
+        let observed = events.clone();
 
-        assert_eq!(manager.dump(), "[2,255]");
+        manager.add_observer(move |event: &IdManagerEvent<u8>| observed.lock().unwrap().push(*event));
 
-        manager.mark_interval_as_used(4, 10);
+        assert_eq!(manager.lease_block(41), Some((10, 50)));
 
-        assert_eq!(manager.dump(), "[2,3], [11,255]");
+        assert_eq!(manager.lease_block(1), None);
 
-        manager.mark_interval_as_used(3, 12);
+        manager.return_block(10, 50);
 
-        assert_eq!(manager.dump(), "[2], [13,255]");
+        assert_eq!(*events.lock().unwrap(), vec![
+            IdManagerEvent::BlockAllocated(10, 50),
+            IdManagerEvent::Exhausted,
+            IdManagerEvent::BlockFreed(10, 50)]);
+    }
 
-        manager.mark_interval_as_used(254, 255);
+    #[test]
+    fn test_bitmap_free_set() {
+        let manager = ThreadSafeIdManager::<u16, BitmapFreeSet<u16>>::new_limited_range(ReuseSlow, 1000, 1999);
 
-        assert_eq!(manager.dump(), "[2], [13,253]");
+        let ids: Vec<_> = (0..10).map(|_| manager.allocate_id()).collect();
 
-        manager.mark_interval_as_used(250, 251);
+        assert_eq!(ids[9].value(), &1009);
 
-        assert_eq!(manager.dump(), "[2], [13,249], [252,253]");
+        assert_eq!(manager.dump(), "[1010,1999]");
 
-        manager.mark_interval_as_used(0, 255);
+        manager.mark_interval_as_used(1500, 1999);
 
-        assert_eq!(manager.dump(), "");
+        drop(ids);
+
+        assert_eq!(manager.dump(), "[1000,1499]");
+
+        assert_eq!(manager.snapshot().to_string(), "policy=ReuseSlow; order=Ascending; ranges=[1000,1999]; next=1010; free=[1000,1499]");
     }
 }
@@ -0,0 +1,488 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::id_manager::IdManager;
+use crate::id_type::IdType;
+use crate::parse_error::ParseError;
+use crate::snapshot::IdManagerSnapshot;
+
+// An operation that changed an IdManager along with its result, formatted in the same way as
+// the operations taken by id-inspect, for example:
+//
+//   allocate -> 23
+//   allocate_block 5 -> 10-14
+//   mark 20-30
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceOp<T: IdType> {
+    Allocate(T),
+    Free(T),
+    Reserve(T),
+    Commit(T),
+    Abort(T),
+    AllocateBlock(u128, Option<(T, T)>),
+    FreeBlock(T, T),
+    MarkAsUsed(T, T),
+    AddRange(T, T),
+    GrowRange(T, T),
+    ShrinkRange(T, T),
+    RemoveRange(T, T),
+    Retire(T, T),
+}
+
+impl<T: IdType> TraceOp<T> {
+    // panics if the result differs from the one that was recorded
+
+    fn replay(&self, manager: &mut IdManager<T>, step: usize) {
+        let actual = match *self {
+            TraceOp::Allocate(_) => TraceOp::Allocate(manager.allocate()),
+            TraceOp::Free(id) => {
+                manager.free(id);
+
+                *self
+            }
+            TraceOp::Reserve(_) => TraceOp::Reserve(manager.reserve()),
+            TraceOp::Commit(id) => {
+                manager.commit(id);
+
+                *self
+            }
+            TraceOp::Abort(id) => {
+                manager.abort(id);
+
+                *self
+            }
+            TraceOp::AllocateBlock(size, _) => TraceOp::AllocateBlock(size, manager.allocate_block(size)),
+            TraceOp::FreeBlock(lower, upper) => {
+                manager.free_block(lower, upper);
+
+                *self
+            }
+            TraceOp::MarkAsUsed(lower, upper) => {
+                manager.mark_interval_as_used(lower, upper);
+
+                *self
+            }
+            TraceOp::AddRange(lower, upper) => Self::expect_ok(manager.add_range(lower, upper), self, step),
+            TraceOp::GrowRange(lower, upper) => Self::expect_ok(manager.grow_range(lower, upper), self, step),
+            TraceOp::ShrinkRange(lower, upper) => Self::expect_ok(manager.shrink_range(lower, upper), self, step),
+            TraceOp::RemoveRange(lower, upper) => Self::expect_ok(manager.remove_range(lower, upper), self, step),
+            TraceOp::Retire(lower, upper) => {
+                manager.retire_interval(lower, upper);
+
+                *self
+            }
+        };
+
+        if actual != *self {
+            panic!("step {}: expected {} but got {}", step, self, actual);
+        }
+    }
+
+    fn expect_ok<E: fmt::Display>(result: Result<(), E>, op: &Self, step: usize) -> Self {
+        match result {
+            Ok(()) => *op,
+            Err(error) => panic!("step {}: {} failed: {}", step, op, error),
+        }
+    }
+}
+
+fn write_interval<T: IdType>(f: &mut fmt::Formatter<'_>, lower: T, upper: T) -> fmt::Result {
+    if lower == upper {
+        write!(f, "{}", lower)
+    } else {
+        write!(f, "{}-{}", lower, upper)
+    }
+}
+
+impl<T: IdType> fmt::Display for TraceOp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, lower, upper) = match *self {
+            TraceOp::Allocate(id) => return write!(f, "allocate -> {}", id),
+            TraceOp::Free(id) => return write!(f, "free {}", id),
+            TraceOp::Reserve(id) => return write!(f, "reserve -> {}", id),
+            TraceOp::Commit(id) => return write!(f, "commit {}", id),
+            TraceOp::Abort(id) => return write!(f, "abort {}", id),
+            TraceOp::AllocateBlock(size, block) => {
+                write!(f, "allocate_block {} -> ", size)?;
+
+                return match block {
+                    Some((lower, upper)) => write_interval(f, lower, upper),
+                    None => write!(f, "none"),
+                };
+            }
+            TraceOp::FreeBlock(lower, upper) => ("free_block", lower, upper),
+            TraceOp::MarkAsUsed(lower, upper) => ("mark", lower, upper),
+            TraceOp::AddRange(lower, upper) => ("add_range", lower, upper),
+            TraceOp::GrowRange(lower, upper) => ("grow_range", lower, upper),
+            TraceOp::ShrinkRange(lower, upper) => ("shrink_range", lower, upper),
+            TraceOp::RemoveRange(lower, upper) => ("remove_range", lower, upper),
+            TraceOp::Retire(lower, upper) => ("retire", lower, upper),
+        };
+
+        write!(f, "{} ", name)?;
+
+        write_interval(f, lower, upper)
+    }
+}
+
+fn parse_value<T: IdType>(text: &str) -> Result<T, ParseError> {
+    text.trim().parse::<T>().map_err(|_| ParseError::InvalidValue(text.trim().to_string()))
+}
+
+fn parse_interval<T: IdType>(text: &str) -> Result<(T, T), ParseError> {
+    match text.split_once('-') {
+        Some((lower, upper)) => Ok((parse_value(lower)?, parse_value(upper)?)),
+        None => {
+            let value = parse_value(text)?;
+
+            Ok((value, value))
+        }
+    }
+}
+
+impl<T: IdType> FromStr for TraceOp<T> {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (call, result) = match text.split_once("->") {
+            Some((call, result)) => (call.trim(), Some(result.trim())),
+            None => (text.trim(), None),
+        };
+
+        let (name, argument) = call.split_once(' ').unwrap_or((call, ""));
+
+        let invalid = || ParseError::InvalidFormat(text.trim().to_string());
+
+        let op = match (name, result) {
+            ("allocate", Some(id)) if argument.is_empty() => TraceOp::Allocate(parse_value(id)?),
+            ("free", None) => TraceOp::Free(parse_value(argument)?),
+            ("reserve", Some(id)) if argument.is_empty() => TraceOp::Reserve(parse_value(id)?),
+            ("commit", None) => TraceOp::Commit(parse_value(argument)?),
+            ("abort", None) => TraceOp::Abort(parse_value(argument)?),
+            ("allocate_block", Some("none")) => TraceOp::AllocateBlock(parse_value(argument)?, None),
+            ("allocate_block", Some(block)) => TraceOp::AllocateBlock(parse_value(argument)?, Some(parse_interval(block)?)),
+            ("free_block", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::FreeBlock(lower, upper)
+            }
+            ("mark", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::MarkAsUsed(lower, upper)
+            }
+            ("add_range", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::AddRange(lower, upper)
+            }
+            ("grow_range", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::GrowRange(lower, upper)
+            }
+            ("shrink_range", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::ShrinkRange(lower, upper)
+            }
+            ("remove_range", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::RemoveRange(lower, upper)
+            }
+            ("retire", None) => {
+                let (lower, upper) = parse_interval(argument)?;
+
+                TraceOp::Retire(lower, upper)
+            }
+            _ => return Err(invalid()),
+        };
+
+        Ok(op)
+    }
+}
+
+// The operations made on an IdManager, and their results, since IdManager::start_trace().
+// The first line of a trace file is the snapshot that the trace started from, followed by
+// one operation per line. Replaying a trace that used ReuseRandom gives the same ids as the
+// snapshot holds the state of the random number generator.
+
+#[derive(Clone)]
+pub struct Trace<T: IdType> {
+    initial: IdManagerSnapshot<T>,
+    ops: Vec<TraceOp<T>>,
+}
+
+impl<T: IdType> Trace<T> {
+    pub(crate) fn new(initial: IdManagerSnapshot<T>) -> Self {
+        Trace { initial, ops: Vec::new() }
+    }
+
+    pub(crate) fn record(&mut self, op: TraceOp<T>) {
+        self.ops.push(op);
+    }
+
+    pub fn initial(&self) -> &IdManagerSnapshot<T> {
+        &self.initial
+    }
+
+    pub fn ops(&self) -> &[TraceOp<T>] {
+        &self.ops
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path)?.parse().map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    // re-executes the operations on a manager restored from the initial snapshot, panicking
+    // at the first one whose result differs from the trace
+
+    pub fn replay(&self) -> IdManager<T> {
+        let mut manager = IdManager::<T>::from_snapshot(self.initial.clone()).unwrap();
+
+        for (step, op) in self.ops.iter().enumerate() {
+            op.replay(&mut manager, step + 1);
+        }
+
+        manager
+    }
+}
+
+impl<T: IdType> fmt::Display for Trace<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.initial)?;
+
+        for op in self.ops.iter() {
+            writeln!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: IdType> FromStr for Trace<T> {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+
+        let initial = lines.next().ok_or_else(|| ParseError::InvalidFormat("missing snapshot".to_string()))?.parse()?;
+
+        let ops = lines.map(|line| line.parse()).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Trace { initial, ops })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::thread;
+
+    use crate::reuse_policy::ReusePolicy::{ReuseRandom, ReuseSlow};
+    use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+    use super::*;
+
+    fn traced_manager() -> IdManager<u8> {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        manager.mark_value_as_used(10);
+        manager.start_trace();
+
+        manager.allocate();
+        manager.allocate();
+        manager.free(11);
+        manager.allocate_block(5);
+        manager.allocate_block(100);
+        manager.mark_interval_as_used(40, 45);
+        manager.free_block(13, 17);
+        manager.add_range(60, 70).unwrap();
+        manager.grow_range(5, 70).unwrap();
+        manager.shrink_range(10, 70).unwrap();
+        manager.remove_range(45, 50).unwrap();
+        manager.retire_interval(30, 35);
+
+        // operations that fail don't change the manager and aren't recorded
+
+        assert_eq!(manager.grow_range(20, 30).is_err(), true);
+
+        manager
+    }
+
+    const TRACE: &str = "policy=ReuseSlow; order=Ascending; ranges=[10,50]; next=10; free=[11,50]
+allocate -> 11
+allocate -> 12
+free 11
+allocate_block 5 -> 13-17
+allocate_block 100 -> none
+mark 40-45
+free_block 13-17
+add_range 60-70
+grow_range 5-70
+shrink_range 10-70
+remove_range 45-50
+retire 30-35
+";
+
+    #[test]
+    fn test_record() {
+        let mut manager = traced_manager();
+
+        let trace = manager.take_trace().unwrap();
+
+        assert_eq!(trace.to_string(), TRACE);
+        assert_eq!(trace.ops()[0], TraceOp::Allocate(11));
+        assert_eq!(trace.initial().next_to_allocate, 10);
+
+        // recording stops once the trace has been taken
+
+        manager.allocate();
+
+        assert_eq!(manager.take_trace().is_none(), true);
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut manager = traced_manager();
+
+        let trace = manager.take_trace().unwrap();
+
+        let replayed = trace.replay();
+
+        assert_eq!(replayed.snapshot().to_string(), manager.snapshot().to_string());
+    }
+
+    #[test]
+    fn test_replay_random() {
+        let mut manager = IdManager::<u32>::new_limited_range(ReuseRandom(1234), 1, 100000);
+
+        for _ in 0..10 {
+            manager.allocate();
+        }
+
+        manager.start_trace();
+
+        for _ in 0..100 {
+            let id = manager.allocate();
+
+            if id.is_multiple_of(3) {
+                manager.free(id);
+            }
+        }
+
+        let trace: Trace<u32> = manager.take_trace().unwrap().to_string().parse().unwrap();
+
+        assert_eq!(trace.replay().snapshot().to_string(), manager.snapshot().to_string());
+    }
+
+    #[test]
+    fn test_replay_reservations() {
+        let mut manager = IdManager::<u8>::new_limited_range(ReuseSlow, 10, 50);
+
+        manager.start_trace();
+
+        let first = manager.reserve();
+        let second = manager.reserve();
+
+        manager.commit(second);
+        manager.abort(first);
+        manager.allocate();
+
+        let trace = manager.take_trace().unwrap();
+
+        assert_eq!(trace.to_string().lines().skip(1).collect::<Vec<_>>(), ["reserve -> 10", "reserve -> 11", "commit 11", "abort 10", "allocate -> 10"]);
+
+        let trace: Trace<u8> = trace.to_string().parse().unwrap();
+
+        assert_eq!(trace.replay().snapshot().to_string(), manager.snapshot().to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "step 2: expected allocate -> 13 but got allocate -> 12")]
+    fn test_replay_diverges() {
+        let trace = TRACE.replace("allocate -> 12", "allocate -> 13").parse::<Trace<u8>>().unwrap();
+
+        trace.replay();
+    }
+
+    #[test]
+    #[should_panic(expected = "step 1: grow_range 11-70 failed: new range is not valid for this operation")]
+    fn test_replay_fails() {
+        let trace = "policy=ReuseSlow; order=Ascending; ranges=[10,50]; next=10; free=[10,50]\ngrow_range 11-70";
+
+        trace.parse::<Trace<u8>>().unwrap().replay();
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("".parse::<Trace<u8>>().err(), Some(ParseError::InvalidFormat("missing snapshot".to_string())));
+
+        assert_eq!("allocate".parse::<TraceOp<u8>>(), Err(ParseError::InvalidFormat("allocate".to_string())));
+        assert_eq!("free 1 -> 2".parse::<TraceOp<u8>>(), Err(ParseError::InvalidFormat("free 1 -> 2".to_string())));
+        assert_eq!("unknown 1".parse::<TraceOp<u8>>(), Err(ParseError::InvalidFormat("unknown 1".to_string())));
+        assert_eq!("free 256".parse::<TraceOp<u8>>(), Err(ParseError::InvalidValue("256".to_string())));
+        assert_eq!("mark 1-x".parse::<TraceOp<u8>>(), Err(ParseError::InvalidValue("x".to_string())));
+
+        assert_eq!("mark 7".parse::<TraceOp<u8>>(), Ok(TraceOp::MarkAsUsed(7, 7)));
+        assert_eq!(TraceOp::MarkAsUsed(7u8, 7).to_string(), "mark 7");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let trace = traced_manager().take_trace().unwrap();
+
+        let path = env::temp_dir().join(format!("id-manager-trace-{}.txt", std::process::id()));
+
+        trace.save(&path).unwrap();
+
+        let loaded = Trace::<u8>::load(&path).unwrap();
+
+        assert_eq!(loaded.to_string(), TRACE);
+
+        std::fs::write(&path, "policy=ReuseSlow").unwrap();
+
+        assert_eq!(Trace::<u8>::load(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_thread_safe_trace() {
+        let manager = ThreadSafeIdManager::<u16>::new_limited_range(ReuseRandom(99), 1, 1000);
+
+        manager.start_trace();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let manager = manager.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        let id = manager.allocate_id();
+
+                        drop(id);
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let trace = manager.take_trace().unwrap();
+
+        assert_eq!(trace.ops().len(), 400);
+
+        assert_eq!(trace.replay().snapshot().to_string(), manager.snapshot().to_string());
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::free_set::FreeSet;
+use crate::id_type::IdType;
+use crate::intervals::Intervals;
+use crate::thread_safe_id_manager::ThreadSafeIdManager;
+
+// The ids that are held by TtlLeases along with when each expires, or None if the time to
+// live is too long to be represented as an Instant, in which case the lease never expires.
+// Each lease has its own generation so that a lease whose id has expired and been allocated
+// again can't renew or free the id of the newer lease.
+
+pub(crate) struct LeaseTable<T: IdType> {
+    leases: BTreeMap<T, (u64, Option<Instant>)>,
+    next_generation: u64,
+}
+
+impl<T: IdType> LeaseTable<T> {
+    pub fn new() -> Self {
+        LeaseTable { leases: BTreeMap::new(), next_generation: 0 }
+    }
+
+    pub fn insert(&mut self, id: T, expires: Option<Instant>) -> u64 {
+        let generation = self.next_generation;
+
+        self.next_generation += 1;
+
+        self.leases.insert(id, (generation, expires));
+
+        generation
+    }
+
+    pub fn contains(&self, id: T, generation: u64) -> bool {
+        matches!(self.leases.get(&id), Some((current, _)) if *current == generation)
+    }
+
+    pub fn expires(&self, id: T, generation: u64) -> Option<Instant> {
+        match self.leases.get(&id) {
+            Some((current, expires)) if *current == generation => *expires,
+            _ => None,
+        }
+    }
+
+    pub fn renew(&mut self, id: T, generation: u64, expires: Option<Instant>) -> bool {
+        match self.leases.get_mut(&id) {
+            Some((current, lease_expires)) if *current == generation => {
+                *lease_expires = expires;
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn remove(&mut self, id: T, generation: u64) -> bool {
+        if !self.contains(id, generation) {
+            return false;
+        }
+
+        self.leases.remove(&id);
+
+        true
+    }
+
+    pub fn remove_expired(&mut self, now: Instant) -> Vec<T> {
+        let expired: Vec<T> = self
+            .leases
+            .iter()
+            .filter(|(_, (_, expires))| expires.is_some_and(|expires| expires <= now))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in expired.iter() {
+            self.leases.remove(id);
+        }
+
+        expired
+    }
+}
+
+// An id that is reclaimed by ThreadSafeIdManager::reap_expired() unless it is renewed before
+// its time to live elapses, or freed when the lease is dropped if it hasn't expired.
+
+pub struct TtlLease<T: IdType, S: FreeSet<T> = Intervals<T>> {
+    manager: ThreadSafeIdManager<T, S>,
+    id: T,
+    generation: u64,
+    ttl: Duration,
+    we_own_id: bool,
+}
+
+impl<T: IdType, S: FreeSet<T>> TtlLease<T, S> {
+    pub(crate) fn new(manager: ThreadSafeIdManager<T, S>, id: T, generation: u64, ttl: Duration) -> Self {
+        TtlLease { manager, id, generation, ttl, we_own_id: true }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.id
+    }
+
+    // None once the lease has expired and its id has been reclaimed, or if it never expires
+
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.manager.leases().expires(self.id, self.generation)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        !self.manager.leases().contains(self.id, self.generation)
+    }
+
+    // extends the lease by its time to live from now, returns false if it has already been
+    // reclaimed
+
+    pub fn renew(&self) -> bool {
+        self.manager.leases().renew(self.id, self.generation, expiry(self.ttl))
+    }
+
+    // keeps the id allocated, without expiring, after the lease is dropped
+
+    pub fn release(&mut self) -> Option<T> {
+        self.we_own_id = false;
+
+        if !self.manager.leases().remove(self.id, self.generation) {
+            return None;
+        }
+
+        Some(self.id)
+    }
+}
+
+impl<T: IdType, S: FreeSet<T>> fmt::Display for TtlLease<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.id)
+    }
+}
+
+impl<T: IdType, S: FreeSet<T>> Drop for TtlLease<T, S> {
+    fn drop(&mut self) {
+        if self.we_own_id && self.manager.leases().remove(self.id, self.generation) {
+            self.manager.free(self.id);
+        }
+    }
+}
+
+// None when the time to live is too long for the lease to ever expire, e.g. Duration::MAX
+
+pub(crate) fn expiry(ttl: Duration) -> Option<Instant> {
+    Instant::now().checked_add(ttl)
+}
+
+// Calls reap_expired() every period until it is dropped
+
+pub struct LeaseReaper {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LeaseReaper {
+    pub(crate) fn start<T: IdType, S: FreeSet<T>>(manager: ThreadSafeIdManager<T, S>, period: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = thread::spawn(move || {
+            // the sender is only ever dropped, which wakes the thread straight away
+
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(period) {
+                manager.reap_expired(Instant::now());
+            }
+        });
+
+        LeaseReaper { stop: Some(stop), thread: Some(thread) }
+    }
+}
+
+impl Drop for LeaseReaper {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::observer::IdManagerEvent;
+    use crate::reuse_policy::ReusePolicy::{ReuseFast, ReuseSlow};
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_lease_expires() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let lease = manager.allocate_lease(TTL);
+
+        assert_eq!(lease.value(), &10);
+        assert_eq!(lease.is_expired(), false);
+        assert_eq!(manager.dump(), "[11,20]");
+
+        assert_eq!(manager.reap_expired(Instant::now()), 0);
+        assert_eq!(manager.reap_expired(Instant::now() + TTL), 1);
+
+        assert_eq!(lease.is_expired(), true);
+        assert_eq!(lease.renew(), false);
+        assert_eq!(manager.dump(), "[10,20]");
+
+        drop(lease);
+
+        assert_eq!(manager.dump(), "[10,20]");
+    }
+
+    #[test]
+    fn test_drop_frees_id() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let lease = manager.allocate_lease(TTL);
+
+        drop(lease);
+
+        assert_eq!(manager.dump(), "[10,20]");
+
+        assert_eq!(manager.reap_expired(Instant::now() + TTL), 0);
+    }
+
+    #[test]
+    fn test_renew() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let lease = manager.allocate_lease(TTL);
+
+        let expires = lease.expires_at().unwrap();
+
+        assert_eq!(lease.renew(), true);
+
+        let renewed = lease.expires_at().unwrap();
+
+        assert_eq!(renewed >= expires, true);
+
+        assert_eq!(manager.reap_expired(renewed - Duration::from_millis(1)), 0);
+        assert_eq!(manager.reap_expired(renewed), 1);
+    }
+
+    #[test]
+    fn test_expired_lease_does_not_touch_reallocated_id() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseFast, 10, 10);
+
+        let mut old = manager.allocate_lease(TTL);
+
+        manager.reap_expired(Instant::now() + TTL);
+
+        let new = manager.allocate_lease(TTL);
+
+        assert_eq!(new.value(), &10);
+
+        assert_eq!(old.renew(), false);
+        assert_eq!(old.release(), None);
+
+        drop(old);
+
+        assert_eq!(new.is_expired(), false);
+        assert_eq!(manager.dump(), "");
+    }
+
+    #[test]
+    fn test_release() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let mut lease = manager.allocate_lease(TTL);
+
+        assert_eq!(lease.release(), Some(10));
+
+        assert_eq!(manager.reap_expired(Instant::now() + TTL), 0);
+
+        drop(lease);
+
+        assert_eq!(manager.dump(), "[11,20]");
+    }
+
+    #[test]
+    fn test_observer_is_notified_of_expiry() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let _lease = manager.allocate_lease(TTL);
+
+        {
+            let events = events.clone();
+
+            manager.add_observer(move |event: &IdManagerEvent<u8>| events.lock().unwrap().push(*event));
+        }
+
+        manager.reap_expired(Instant::now() + TTL);
+
+        assert_eq!(*events.lock().unwrap(), vec![IdManagerEvent::Expired(10), IdManagerEvent::Freed(10)]);
+    }
+
+    #[test]
+    fn test_lease_that_never_expires() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let lease = manager.allocate_lease(Duration::MAX);
+
+        assert_eq!(lease.expires_at(), None);
+        assert_eq!(lease.is_expired(), false);
+        assert_eq!(lease.renew(), true);
+
+        assert_eq!(manager.reap_expired(Instant::now() + Duration::from_secs(1_000_000)), 0);
+        assert_eq!(lease.is_expired(), false);
+
+        drop(lease);
+
+        assert_eq!(manager.dump(), "[10,20]");
+    }
+
+    #[test]
+    fn test_reaper() {
+        let manager = ThreadSafeIdManager::<u8>::new_limited_range(ReuseSlow, 10, 20);
+
+        let reaper = manager.start_lease_reaper(Duration::from_millis(5));
+
+        let lease = manager.allocate_lease(Duration::from_millis(10));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while !lease.is_expired() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(lease.is_expired(), true);
+
+        drop(reaper);
+
+        assert_eq!(manager.dump(), "[10,20]");
+    }
+}
@@ -0,0 +1,59 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::step::Step;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum IntervalsViolation<T: Step> {
+    Inverted((T, T)),
+    Unsorted((T, T), (T, T)),
+    Overlapping((T, T), (T, T)),
+    Unmerged((T, T), (T, T)),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ValidationReport<T: Step> {
+    pub dump: String,
+    pub violations: Vec<IntervalsViolation<T>>,
+}
+
+fn format_interval<T: Step>((lower, upper): &(T, T)) -> String {
+    if lower == upper {
+        format!("[{}]", lower)
+    } else {
+        format!("[{},{}]", lower, upper)
+    }
+}
+
+impl<T: Step> fmt::Display for IntervalsViolation<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntervalsViolation::Inverted(interval) => {
+                write!(f, "{} is inverted", format_interval(interval))
+            }
+            IntervalsViolation::Unsorted(first, second) => {
+                write!(f, "{} is stored before {}", format_interval(first), format_interval(second))
+            }
+            IntervalsViolation::Overlapping(first, second) => {
+                write!(f, "{} overlaps {}", format_interval(first), format_interval(second))
+            }
+            IntervalsViolation::Unmerged(first, second) => {
+                write!(f, "{} and {} are adjacent but not merged", format_interval(first), format_interval(second))
+            }
+        }
+    }
+}
+
+impl<T: Step> fmt::Display for ValidationReport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid intervals {}", self.dump)?;
+
+        for violation in self.violations.iter() {
+            write!(f, "\n  {}", violation)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Step + fmt::Debug> Error for ValidationReport<T> {}